@@ -0,0 +1,171 @@
+//! `cargo compiletest`: builds the current package and runs its compiletest suites, without a
+//! hand-written `#[test] fn compile_test()` harness.
+//!
+//! The suites come from a `compiletest.toml` next to `Cargo.toml` if there is one, and are
+//! otherwise whichever of `tests/ui`, `tests/compile-fail` and `tests/run-pass` exist.
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+extern crate getopts;
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use crate::compiletest::common::Mode;
+use crate::compiletest::deps::Dependencies;
+use crate::compiletest::suites::{self, Suite};
+use crate::compiletest::Config;
+
+const DEFAULT_SUITES: &[&str] = &["ui", "compile-fail", "run-pass"];
+
+fn main() {
+    // Cargo runs `cargo-compiletest compiletest <args>`
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+    if args.first().map(String::as_str) == Some("compiletest") {
+        args.remove(0);
+    }
+
+    let mut opts = getopts::Options::new();
+    opts.optflag(
+        "",
+        "bless",
+        "overwrite expected output files with the actual output",
+    )
+    .optmulti("", "mode", "only run suites of this mode", "MODE")
+    .optopt("", "edition", "default edition of the tests", "EDITION")
+    .optflag(
+        "",
+        "exact",
+        "match filters exactly, rather than as substrings",
+    )
+    .optmulti(
+        "",
+        "features",
+        "features to build the package with",
+        "FEATURES",
+    )
+    .optflag("q", "quiet", "print one character per test")
+    .optflag("h", "help", "print this message");
+    let usage = || opts.usage("Usage: cargo compiletest [options] [FILTER...]");
+    let matches = match opts.parse(&args) {
+        Ok(matches) => matches,
+        Err(e) => fail(&format!("{}\n\n{}", e, usage())),
+    };
+    if matches.opt_present("help") {
+        println!("{}", usage());
+        return;
+    }
+    let modes = matches
+        .opt_strs("mode")
+        .iter()
+        .map(|mode| {
+            mode.parse::<Mode>()
+                .unwrap_or_else(|()| fail(&format!("unknown mode `{}`", mode)))
+        })
+        .collect::<Vec<_>>();
+
+    let root = package_root();
+    let features = matches
+        .opt_strs("features")
+        .iter()
+        .flat_map(|features| features.split([',', ' ']))
+        .filter(|feature| !feature.is_empty())
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+    let mut cargo_args = Vec::new();
+    for features in matches.opt_strs("features") {
+        cargo_args.push("--features".to_owned());
+        cargo_args.push(features);
+    }
+    cargo_args.push("--lib".to_owned());
+    cargo_args.push("--tests".to_owned());
+    let cargo_args = cargo_args.iter().map(String::as_str).collect::<Vec<_>>();
+    let dependencies = Dependencies::from_cargo_build(&cargo_args)
+        .unwrap_or_else(|e| fail(&format!("failed to build the package: {}", e)));
+
+    let suites = find_suites(&root);
+    if suites.is_empty() {
+        fail("no suites found in compiletest.toml or tests/{ui,compile-fail,run-pass}");
+    }
+    let mut failed = false;
+    for suite in suites {
+        if !modes.is_empty() && !modes.contains(&suite.config.mode) {
+            continue;
+        }
+        let mut config = suite.config;
+        config.bless |= matches.opt_present("bless");
+        config.filters.extend(matches.free.iter().cloned());
+        config.filter_exact |= matches.opt_present("exact");
+        config.quiet |= matches.opt_present("quiet");
+        if let Some(edition) = matches.opt_str("edition") {
+            config.edition = Some(edition);
+        }
+        config.dependencies = dependencies.clone();
+        config.features.extend(features.iter().cloned());
+        if let Err(e) = fs::create_dir_all(&config.build_base) {
+            fail(&format!(
+                "failed to create {}: {}",
+                config.build_base.display(),
+                e
+            ));
+        }
+
+        println!("\nsuite {} ({})", suite.name, config.src_base.display());
+        match compiletest::run_tests_with_summary(&config) {
+            Ok(summary) => failed |= !summary.is_success(),
+            Err(e) => fail(&e.to_string()),
+        }
+    }
+    if failed {
+        process::exit(101);
+    }
+}
+
+/// The directory of the `Cargo.toml` of the package in the current directory.
+fn package_root() -> PathBuf {
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo"));
+    let output = Command::new(cargo)
+        .args(["locate-project", "--message-format", "plain"])
+        .output()
+        .unwrap_or_else(|e| fail(&format!("failed to run cargo: {}", e)));
+    if !output.status.success() {
+        fail(String::from_utf8_lossy(&output.stderr).trim());
+    }
+    let manifest = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+    manifest.parent().unwrap().to_owned()
+}
+
+fn find_suites(root: &Path) -> Vec<Suite> {
+    let config_file = root.join(suites::CONFIG_FILE);
+    if config_file.is_file() {
+        return suites::load(&config_file).unwrap_or_else(|e| fail(&e.to_string()));
+    }
+
+    let target_dir =
+        env::var_os("CARGO_TARGET_DIR").map_or_else(|| root.join("target"), PathBuf::from);
+    DEFAULT_SUITES
+        .iter()
+        .filter(|name| root.join("tests").join(name).is_dir())
+        .map(|name| {
+            let config = Config::detect()
+                .mode(name.parse().unwrap())
+                .src_base(root.join("tests").join(name))
+                .build_base(target_dir.join("compiletest").join(name))
+                .build()
+                .unwrap_or_else(|e| fail(&e.to_string()));
+            Suite {
+                name: name.to_string(),
+                config,
+            }
+        })
+        .collect()
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
+}
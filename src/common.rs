@@ -10,17 +10,27 @@
 pub use self::Mode::*;
 
 use std::env;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::fs::{read_dir, remove_file};
 use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
+use serde::de::{self, Deserialize, Deserializer};
+
+use crate::deps::Dependencies;
+use crate::hooks::Hook;
+use crate::list::ListFormat;
+use crate::runner::CustomMode;
 use crate::runtest::dylib_env_var;
 use crate::test::ColorConfig;
+use crate::util;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
+#[non_exhaustive]
 pub enum Mode {
     CompileFail,
     ParseFail,
@@ -38,6 +48,8 @@ pub enum Mode {
     Ui,
     MirOpt,
     Assembly,
+    /// A mode registered with `runner::register_mode`
+    Custom(CustomMode),
 }
 
 impl Mode {
@@ -74,11 +86,24 @@ impl FromStr for Mode {
             "ui" => Ok(Ui),
             "mir-opt" => Ok(MirOpt),
             "assembly" => Ok(Assembly),
-            _ => Err(()),
+            _ => crate::runner::custom_mode(s).ok_or(()),
         }
     }
 }
 
+impl<'de> Deserialize<'de> for Mode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Mode, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(|()| {
+            de::Error::custom(format!(
+                "unknown mode `{}`; custom modes must be registered with \
+                     `runner::register_mode` first",
+                s
+            ))
+        })
+    }
+}
+
 impl fmt::Display for Mode {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         fmt::Display::fmt(
@@ -99,13 +124,46 @@ impl fmt::Display for Mode {
                 Ui => "ui",
                 MirOpt => "mir-opt",
                 Assembly => "assembly",
+                Custom(mode) => mode.name(),
             },
             f,
         )
     }
 }
 
-#[derive(Clone)]
+/// A built-in normalization of stdout and stderr, turned on with `Config::normalize_presets`.
+/// Written in kebab-case in a `compiletest.toml`, e.g. `normalize_presets = ["hex"]`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NormalizePreset {
+    /// Hexadecimal numbers like addresses, e.g. `0x7ffd5e2c` → `$HEX`
+    Hex,
+    /// 16-digit hexadecimal hashes, like those in the file names of crates → `$HASH`
+    Hash,
+    /// The release of the compiler, e.g. `1.80.0-nightly` → `$RUSTC_VERSION`
+    RustcVersion,
+}
+
+impl NormalizePreset {
+    /// The regex replacement of this preset, if it applies to `config`
+    pub fn rule(self, config: &Config) -> Option<(String, String)> {
+        let (from, to) = match self {
+            NormalizePreset::Hex => (r"\b0x[0-9a-fA-F]+\b".to_owned(), "$$HEX"),
+            NormalizePreset::Hash => (r"\b[0-9a-f]{16}\b".to_owned(), "$$HASH"),
+            NormalizePreset::RustcVersion => (
+                regex::escape(&util::rustc_release(&config.rustc_path)?),
+                "$$RUSTC_VERSION",
+            ),
+        };
+        Some((from, to.to_owned()))
+    }
+}
+
+/// Can be deserialized, e.g. from a `compiletest.toml` (see `suites`), with the field names as
+/// keys. Missing fields take their `Default` values, `timeout` is in seconds and `color` is one of
+/// `auto`, `always` or `never`.
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct Config {
     /// `true` to overwrite stderr/stdout/fixed files instead of complaining about changes in output.
     pub bless: bool,
@@ -129,6 +187,7 @@ pub struct Config {
     pub docck_python: String,
 
     /// A function to run before FileCheck.
+    #[serde(skip)]
     pub llvm_filecheck_preprocess: Option<fn(&Path, &Path)>,
 
     /// The llvm FileCheck binary path
@@ -144,6 +203,16 @@ pub struct Config {
     /// The directory containing the tests to run
     pub src_base: PathBuf,
 
+    /// More directories of tests, collected after those in `src_base`. Their tests are named
+    /// and built after the last component of the directory, e.g. `[ui] shared/foo.rs` and
+    /// `<build_base>/shared/foo`, so that component must differ from those of the other
+    /// directories and from the names of the subdirectories of `src_base`.
+    pub extra_src_bases: Vec<PathBuf>,
+
+    /// Directories searched, in order, for the files of `aux-build` headers that are not in
+    /// the `auxiliary` directory next to the test
+    pub aux_dirs: Vec<PathBuf>,
+
     /// The directory where programs should be built
     pub build_base: PathBuf,
 
@@ -156,15 +225,53 @@ pub struct Config {
     /// Run ignored tests
     pub run_ignored: bool,
 
+    /// Skip tests that passed before and whose inputs haven't changed since: the test file, its
+    /// auxiliary files, its expected output files, the compiler, the flags in this `Config` and
+    /// the libraries it links with, from `dependencies` or the `-L` directories in the flags.
+    /// Skipped tests are reported as ignored.
+    pub skip_up_to_date: bool,
+
+    /// Only run the tests that failed when they last ran, as recorded in `failed-tests.txt` in
+    /// `build_base` by every run. Runs all tests if there is no such file yet.
+    pub rerun_failed: bool,
+
     /// Only run tests that match these filters
     pub filters: Vec<String>,
 
     /// Exactly match the filter, rather than a substring
     pub filter_exact: bool,
 
+    /// Number of tests to run in parallel. Defaults to `RUST_TEST_THREADS`, or the number of CPUs.
+    pub test_threads: Option<usize>,
+
+    /// Stop starting new tests after the first failure
+    pub fail_fast: bool,
+
+    /// Run the tests in an order shuffled with this seed, rather than alphabetically. Reusing a
+    /// seed reproduces the order.
+    pub shuffle_seed: Option<u64>,
+
+    /// Only run the tests of shard `shard_index` (counting from 0) out of `shard_count`, to split
+    /// a suite across machines. Tests are assigned to shards by a stable hash of their name, or
+    /// with `shard_weights`.
+    pub shard_index: usize,
+    pub shard_count: usize,
+
+    /// The `report.jsonl` files of a previous run (see `write_report`), typically one from each
+    /// shard, as every shard only reports its own tests. If set, tests are assigned to shards so
+    /// that the test durations recorded in them are spread evenly.
+    pub shard_weights: Vec<PathBuf>,
+
     /// Write out a parseable log of tests that were run
     pub logfile: Option<PathBuf>,
 
+    /// Print the tests that would be run in this format instead of running them
+    pub list: Option<ListFormat>,
+
+    /// Write a JUnit XML report (`report.xml`) and a JSON-lines event file (`report.jsonl`)
+    /// of the test run into `build_base`
+    pub write_report: bool,
+
     /// A command line to prefix program execution with,
     /// for running under valgrind
     pub runtool: Option<String>,
@@ -175,6 +282,25 @@ pub struct Config {
     /// Flags to pass to the compiler when building for the target
     pub target_rustcflags: Option<String>,
 
+    /// Arguments to pass to the compiler when building for the target, after `target_rustcflags`.
+    /// Unlike those, they aren't split, so they can be paths that aren't valid UTF-8, like the
+    /// `-L` flags added by `link_deps`.
+    #[serde(skip)]
+    pub target_rustc_args: Vec<OsString>,
+
+    /// Crates to pass to the compiler with `--extern`, and where their dependencies are, e.g. as
+    /// set by `link_cargo_deps`
+    #[serde(skip)]
+    pub dependencies: Dependencies,
+
+    /// Cargo features enabled for the crate under test, for the `needs-feature` and
+    /// `ignore-feature` headers and feature-specific expected output like `foo.serde.stderr`.
+    /// Defaults to the features of the `CARGO_FEATURE_*` environment variables, lowercased, which
+    /// cargo only sets for build scripts; a test harness fills this with `enabled_features!`.
+    /// The order matters when a test has output files for several enabled features: the first
+    /// feature with a file wins.
+    pub features: Vec<String>,
+
     /// Target system to be tested
     pub target: String,
 
@@ -193,6 +319,9 @@ pub struct Config {
     /// Version of LLDB
     pub lldb_version: Option<String>,
 
+    /// Release channel of the compiler: `stable`, `beta`, `nightly` or `dev`
+    pub channel: Option<String>,
+
     /// Version of LLVM
     pub llvm_version: Option<String>,
 
@@ -221,6 +350,7 @@ pub struct Config {
     pub quiet: bool,
 
     /// Whether to use colors in test.
+    #[serde(deserialize_with = "deserialize_color")]
     pub color: ColorConfig,
 
     /// where to find the remote test client process, if we're using it
@@ -242,6 +372,26 @@ pub struct Config {
     /// Expected exit code for compile tests. Defaults to expecting `1` if unset.
     pub compile_test_exit_code: Option<i32>,
 
+    /// Regex replacements like those of the `normalize-stdout` header, applied to the stdout of
+    /// every test after the built-in path normalizations and before the test's own rules
+    pub normalize_stdout: Vec<(String, String)>,
+
+    /// Like `normalize_stdout`, for stderr
+    pub normalize_stderr: Vec<(String, String)>,
+
+    /// Built-in normalizations applied to stdout and stderr before `normalize_stdout` and
+    /// `normalize_stderr`
+    pub normalize_presets: Vec<NormalizePreset>,
+
+    /// Kill the compiler or test program, and fail the test, once it has run for this long.
+    /// A test can override this with a `timeout: <secs>` header.
+    #[serde(deserialize_with = "deserialize_timeout")]
+    pub timeout: Option<Duration>,
+
+    /// Called around every compiler invocation and program execution of a test, see `hooks`
+    #[serde(skip)]
+    pub hooks: Vec<Arc<dyn Hook>>,
+
     // Configuration for various run-make tests frobbing things like C compilers
     // or querying about various LLVM component information.
     pub cc: String,
@@ -254,6 +404,21 @@ pub struct Config {
     pub nodejs: Option<String>,
 }
 
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ColorConfig, D::Error> {
+    match &*String::deserialize(deserializer)? {
+        "auto" => Ok(ColorConfig::AutoColor),
+        "always" => Ok(ColorConfig::AlwaysColor),
+        "never" => Ok(ColorConfig::NeverColor),
+        s => Err(de::Error::unknown_variant(s, &["auto", "always", "never"])),
+    }
+}
+
+fn deserialize_timeout<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_secs))
+}
+
 #[derive(Clone)]
 pub struct TestPaths {
     pub file: PathBuf,         // e.g., compile-test/foo/bar/baz.rs
@@ -261,18 +426,102 @@ pub struct TestPaths {
     pub relative_dir: PathBuf, // e.g., foo/bar
 }
 
-/// Used by `ui` tests to generate things like `foo.stderr` from `foo.rs`.
-pub fn expected_output_path(testpaths: &TestPaths, revision: Option<&str>, kind: &str) -> PathBuf {
-    assert!(UI_EXTENSIONS.contains(&kind));
-    let mut parts = Vec::new();
+impl TestPaths {
+    /// The file that is compiled and read for headers and `//~` annotations: `file`, or the
+    /// crate root if `file` is a multi-file test directory.
+    pub fn root_file(&self) -> PathBuf {
+        test_crate_root(&self.file).unwrap_or_else(|| self.file.clone())
+    }
+}
 
-    if let Some(x) = revision {
-        parts.push(x);
+/// The `main.rs` or, failing that, `lib.rs` in `dir`, if `dir` is a directory that has one.
+pub fn test_crate_root(dir: &Path) -> Option<PathBuf> {
+    if !dir.is_dir() {
+        return None;
     }
-    parts.push(kind);
+    ["main.rs", "lib.rs"]
+        .iter()
+        .map(|root| dir.join(root))
+        .find(|root| root.is_file())
+}
+
+/// Used by `ui` tests to generate things like `foo.stderr` from `foo.rs`, and by custom modes
+/// for the kinds of their `TestRunner::output_kinds`.
+pub fn expected_output_path(testpaths: &TestPaths, revision: Option<&str>, kind: &str) -> PathBuf {
+    first_existing(output_paths(&[], testpaths, revision, kind))
+}
+
+/// Like `expected_output_path`, except that a file for one of `config.features`, like
+/// `foo.serde.stderr`, is used instead if it exists, trying the features in order.
+pub fn expected_output_path_with_features(
+    config: &Config,
+    testpaths: &TestPaths,
+    revision: Option<&str>,
+    kind: &str,
+) -> PathBuf {
+    first_existing(expected_output_paths(config, testpaths, revision, kind))
+}
+
+/// The files `expected_output_path_with_features` considers, in order: one for each of
+/// `config.features`, then the one for any features.
+pub fn expected_output_paths(
+    config: &Config,
+    testpaths: &TestPaths,
+    revision: Option<&str>,
+    kind: &str,
+) -> Vec<PathBuf> {
+    output_paths(&config.features, testpaths, revision, kind)
+}
+
+fn output_paths(
+    features: &[String],
+    testpaths: &TestPaths,
+    revision: Option<&str>,
+    kind: &str,
+) -> Vec<PathBuf> {
+    let features = features.iter().map(|feature| Some(feature.as_str()));
+    features
+        .chain(Some(None))
+        .map(|feature| {
+            let parts = [revision, feature, Some(kind)];
+            let extension = parts
+                .iter()
+                .flatten()
+                .copied()
+                .collect::<Vec<_>>()
+                .join(".");
+            testpaths.file.with_extension(extension)
+        })
+        .collect()
+}
+
+/// The first of `paths` that exists, or else the last one.
+fn first_existing(mut paths: Vec<PathBuf>) -> PathBuf {
+    let default = paths.pop().unwrap();
+    paths
+        .into_iter()
+        .find(|path| path.exists())
+        .unwrap_or(default)
+}
 
-    let extension = parts.join(".");
-    testpaths.file.with_extension(extension)
+/// The file of an `aux-build: <rel_ab>` header of a test: `rel_ab` in the `auxiliary` directory
+/// next to the test, or else in the first of `config.aux_dirs` that has it. Returns the path in
+/// `auxiliary` if none has it.
+pub fn aux_file_path(config: &Config, testpaths: &TestPaths, rel_ab: &str) -> PathBuf {
+    let test_dir = testpaths
+        .file
+        .parent()
+        .expect("test file path has no parent");
+    let local = test_dir.join("auxiliary").join(rel_ab);
+    if local.exists() {
+        return local;
+    }
+    config
+        .aux_dirs
+        .iter()
+        .map(|dir| dir.join(rel_ab))
+        .find(|path| path.exists())
+        .unwrap_or(local)
 }
 
 pub const UI_EXTENSIONS: &[&str] = &[UI_STDERR, UI_STDOUT, UI_FIXED];
@@ -281,18 +530,16 @@ pub const UI_STDOUT: &str = "stdout";
 pub const UI_FIXED: &str = "fixed";
 
 /// Returns additional link flags for the given `lib_paths`.
-fn lib_paths_flags<T>(lib_paths: T) -> String
+fn lib_paths_flags<T>(lib_paths: T) -> Vec<OsString>
 where
     T: AsRef<OsStr>,
 {
-    let mut flags = String::new();
+    let mut flags = Vec::new();
 
     if !lib_paths.as_ref().is_empty() {
         for p in env::split_paths(lib_paths.as_ref()) {
-            let p = p.to_str().unwrap();
-            assert!(!p.contains(' '), "spaces in paths not supported: {}", p);
-            flags += " -L ";
-            flags += p;
+            flags.push(OsString::from("-L"));
+            flags.push(p.into_os_string());
         }
     }
 
@@ -308,17 +555,31 @@ impl Config {
         // link flags
         let lib_paths = env::var_os(varname).unwrap_or_default();
 
-        // Append to current flags if any are set, otherwise make new String
-        let mut flags = self.target_rustcflags.take().unwrap_or_default();
-        flags += lib_paths_flags(&lib_paths).as_str();
+        self.target_rustc_args.extend(lib_paths_flags(&lib_paths));
+    }
 
-        self.target_rustcflags = Some(flags);
+    /// Pass the exact libraries built by `cargo build --lib --tests` to rustc with `--extern`, so
+    /// that tests can `extern crate` the crate under test and its direct (dev-)dependencies
+    /// without E0464 ("multiple candidates") errors, whatever else is in `target/*/deps`. `args`
+    /// are passed on to cargo, e.g. `&["--features", "foo"]` to match the features the tests are
+    /// run with.
+    ///
+    /// Panics if `cargo build` fails, or if a crate name is ambiguous (see
+    /// `Dependencies::from_cargo_messages`).
+    pub fn link_cargo_deps(&mut self, args: &[&str]) {
+        let args = [&["--lib", "--tests"], args].concat();
+        self.dependencies = Dependencies::from_cargo_build(&args)
+            .unwrap_or_else(|e| panic!("failed to resolve dependencies with cargo: {}", e));
     }
 
     fn find_deps_with_extension(&self, ext: &'static str) -> impl Iterator<Item = PathBuf> + '_ {
         self.target_rustcflags
             .iter()
-            .flat_map(|flags| flags.split_whitespace().filter(|s| s.ends_with("/deps")))
+            .flat_map(|flags| util::split_args(flags).unwrap_or_default())
+            .map(OsString::from)
+            .chain(self.target_rustc_args.iter().cloned())
+            .map(PathBuf::from)
+            .filter(|directory| directory.ends_with("deps"))
             .filter_map(|directory| read_dir(directory).ok())
             .flat_map(move |entries| {
                 entries.filter_map(Result::ok).filter_map(move |entry| {
@@ -329,11 +590,57 @@ impl Config {
             })
     }
 
+    /// The Rust libraries in the `-L` directories of `target_rustcflags` and `target_rustc_args`,
+    /// such as the crate under test when it is linked with `link_deps`.
+    pub(crate) fn linked_libraries(&self) -> Vec<PathBuf> {
+        let args = self
+            .target_rustcflags
+            .iter()
+            .flat_map(|flags| util::split_args(flags).unwrap_or_default())
+            .map(OsString::from)
+            .chain(self.target_rustc_args.iter().cloned())
+            .collect::<Vec<_>>();
+
+        let mut directories = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let path = if arg == "-L" {
+                args.next().cloned()
+            } else {
+                arg.to_str()
+                    .and_then(|arg| arg.strip_prefix("-L"))
+                    .map(OsString::from)
+            };
+            if let Some(path) = path {
+                // `-L` takes an optional kind of search path, as in `-L dependency=target/deps`
+                let kinds = ["dependency=", "crate=", "native=", "framework=", "all="];
+                let stripped = path
+                    .to_str()
+                    .and_then(|s| kinds.iter().find_map(|kind| s.strip_prefix(kind)))
+                    .map(PathBuf::from);
+                directories.push(stripped.unwrap_or_else(|| PathBuf::from(path)));
+            }
+        }
+
+        directories
+            .iter()
+            .filter_map(|directory| read_dir(directory).ok())
+            .flat_map(|entries| entries.filter_map(Result::ok).map(|entry| entry.path()))
+            .filter(|path| {
+                matches!(
+                    path.extension().and_then(OsStr::to_str),
+                    Some("rlib" | "rmeta" | "so" | "dylib" | "dll")
+                )
+            })
+            .collect()
+    }
+
     /// Remove rmeta files from target `deps` directory
     ///
     /// These files are created by `cargo check`, and conflict with
     /// `cargo build` rlib files, causing E0464 for tests which use
-    /// the parent crate.
+    /// the parent crate. `link_cargo_deps` avoids the conflict without
+    /// deleting anything.
     pub fn clean_rmeta(&self) {
         self.find_deps_with_extension("rmeta").for_each(|path| {
             let _: Result<(), _> = remove_file(path);
@@ -345,6 +652,7 @@ impl Config {
     /// These files are created by `cargo build`; running the tests with
     /// multiple sets of features can produce multiple rlib files for
     /// each crate, causing E0464 for tests which use the parent crate.
+    /// `link_cargo_deps` avoids the conflict without deleting anything.
     pub fn clean_rlib(&self) {
         let mut rlibs = self.find_deps_with_extension("rlib").collect::<Vec<_>>();
         let () = rlibs.sort();
@@ -441,16 +749,31 @@ impl Default for Config {
             llvm_filecheck_preprocess: None,
             llvm_filecheck: None,
             src_base: PathBuf::from("tests/run-pass"),
+            extra_src_bases: vec![],
+            aux_dirs: vec![],
             build_base: env::temp_dir(),
             stage_id: "stage-id".to_owned(),
             mode: Mode::RunPass,
             run_ignored: false,
+            skip_up_to_date: false,
+            rerun_failed: false,
             filters: vec![],
             filter_exact: false,
+            test_threads: None,
+            fail_fast: false,
+            shuffle_seed: None,
             logfile: None,
+            list: None,
+            write_report: false,
+            shard_index: 0,
+            shard_count: 1,
+            shard_weights: vec![],
             runtool: None,
             host_rustcflags: None,
             target_rustcflags: None,
+            target_rustc_args: vec![],
+            dependencies: Dependencies::default(),
+            features: util::cargo_features(),
             #[cfg(feature = "rustc")]
             target: platform.clone(),
             #[cfg(not(feature = "rustc"))]
@@ -464,6 +787,7 @@ impl Default for Config {
             gdb_version: None,
             gdb_native_rust: false,
             lldb_version: None,
+            channel: None,
             llvm_version: None,
             system_llvm: false,
             android_cross_path: PathBuf::from("android-cross-path"),
@@ -476,6 +800,11 @@ impl Default for Config {
             color: ColorConfig::AutoColor,
             remote_test_client: None,
             compile_test_exit_code: None,
+            normalize_stdout: vec![],
+            normalize_stderr: vec![],
+            normalize_presets: vec![],
+            timeout: None,
+            hooks: vec![],
             cc: "cc".to_string(),
             cxx: "cxx".to_string(),
             cflags: "cflags".to_string(),
@@ -497,19 +826,27 @@ mod test {
     #[cfg(not(target_os = "windows"))]
     #[test]
     fn test_lib_paths_flags() {
-        assert_eq!(lib_paths_flags(""), "");
-        assert_eq!(lib_paths_flags("/lib"), " -L /lib");
-        assert_eq!(lib_paths_flags("/lib:/usr/lib"), " -L /lib -L /usr/lib");
+        assert!(lib_paths_flags("").is_empty());
+        assert_eq!(lib_paths_flags("/lib"), ["-L", "/lib"]);
+        assert_eq!(
+            lib_paths_flags("/lib:/usr/lib"),
+            ["-L", "/lib", "-L", "/usr/lib"]
+        );
+        assert_eq!(lib_paths_flags("/my lib"), ["-L", "/my lib"]);
+
+        use std::os::unix::ffi::OsStrExt;
+        let not_utf8 = OsStr::from_bytes(b"/l\xffib");
+        assert_eq!(lib_paths_flags(not_utf8), [OsStr::new("-L"), not_utf8]);
     }
 
     #[cfg(target_os = "windows")]
     #[test]
     fn test_lib_paths_flags() {
-        assert_eq!(lib_paths_flags(""), "");
-        assert_eq!(lib_paths_flags("C:\\lib"), " -L C:\\lib");
+        assert!(lib_paths_flags("").is_empty());
+        assert_eq!(lib_paths_flags("C:\\lib"), ["-L", "C:\\lib"]);
         assert_eq!(
             lib_paths_flags("C:\\lib;C:\\usr\\lib"),
-            " -L C:\\lib -L C:\\usr\\lib"
+            ["-L", "C:\\lib", "-L", "C:\\usr\\lib"]
         );
     }
 }
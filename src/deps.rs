@@ -0,0 +1,260 @@
+//! Exact paths to the crates tests may `extern crate`, taken from cargo's JSON build messages
+//! instead of searched for in `target/*/deps`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, BufRead, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Crates to pass to rustc with `--extern name=path`, plus the `-L` search paths their own
+/// dependencies and native libraries are found in.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct Dependencies {
+    /// Crate names (with `-` replaced by `_`) and the rlib, dylib or proc-macro to use for them
+    pub externs: BTreeMap<String, PathBuf>,
+    /// Arguments for `-L`, like `dependency=target/debug/deps` or `native=...`
+    pub search_paths: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+enum Message {
+    CompilerArtifact {
+        package_id: String,
+        target: Target,
+        filenames: Vec<PathBuf>,
+    },
+    BuildScriptExecuted {
+        linked_paths: Vec<String>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct Target {
+    name: String,
+    crate_types: Vec<String>,
+}
+
+/// The parts of `cargo metadata --format-version 1` needed to find the direct dependencies of
+/// the package under test.
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+    resolve: Option<Resolve>,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    id: String,
+    targets: Vec<Target>,
+}
+
+#[derive(Deserialize)]
+struct Resolve {
+    root: Option<String>,
+    nodes: Vec<Node>,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    id: String,
+    deps: Vec<NodeDep>,
+}
+
+#[derive(Deserialize)]
+struct NodeDep {
+    /// The name the dependency is `extern crate`d by, after renaming and with `-` replaced by `_`
+    name: String,
+    pkg: String,
+    dep_kinds: Vec<DepKind>,
+}
+
+#[derive(Deserialize)]
+struct DepKind {
+    /// `None` for normal dependencies, otherwise `dev` or `build`
+    kind: Option<String>,
+}
+
+impl Dependencies {
+    /// Runs `cargo build` with `args` (e.g. `["--lib", "--tests", "--features", "foo"]`) in the
+    /// current directory and collects the library of the package there and of its direct
+    /// dependencies and dev-dependencies, as resolved by `cargo metadata`. `--tests` includes the
+    /// dev-dependencies, but only builds the library itself alongside `--lib`.
+    pub fn from_cargo_build(args: &[&str]) -> io::Result<Self> {
+        let messages =
+            cargo(&[&["build", "--message-format=json-render-diagnostics"], args].concat())?;
+        let metadata = cargo(
+            &[
+                &["metadata", "--format-version", "1"],
+                &metadata_args(args)[..],
+            ]
+            .concat(),
+        )?;
+        Self::from_cargo_messages(&metadata[..], &messages[..])
+    }
+
+    /// Collects the libraries of the root package of `metadata`, the output of `cargo metadata
+    /// --format-version 1`, and of its direct dependencies and dev-dependencies from the output
+    /// of `cargo build --message-format=json`. Lines of `messages` that aren't JSON objects are
+    /// skipped, as are artifacts of `cargo check` (only an `.rmeta`). Every library that was
+    /// built is still searched for the dependencies of these with `-L dependency=...`.
+    ///
+    /// Fails if there is no root package, e.g. in a virtual workspace, or if a crate name would
+    /// refer to more than one library, e.g. because cargo built it twice with different features.
+    pub fn from_cargo_messages(metadata: impl Read, messages: impl BufRead) -> io::Result<Self> {
+        let crate_names = direct_dependencies(serde_json::from_reader(metadata)?)?;
+        let mut candidates = BTreeMap::<String, Vec<PathBuf>>::new();
+        let mut deps = Dependencies::default();
+        for line in messages.lines() {
+            let line = line?;
+            if !line.starts_with('{') {
+                continue;
+            }
+            match serde_json::from_str(&line)? {
+                Message::CompilerArtifact {
+                    package_id,
+                    target,
+                    filenames,
+                } => {
+                    if !target.crate_types.iter().any(|t| is_extern_crate_type(t)) {
+                        continue;
+                    }
+                    let lib = match filenames.into_iter().find(|f| is_linkable(f)) {
+                        Some(lib) => lib,
+                        None => continue,
+                    };
+                    if let Some(dir) = lib.parent() {
+                        deps.add_search_path(format!("dependency={}", dir.display()));
+                    }
+                    if let Some(name) = crate_names.get(&package_id) {
+                        let paths = candidates.entry(name.clone()).or_default();
+                        if !paths.contains(&lib) {
+                            paths.push(lib);
+                        }
+                    }
+                }
+                Message::BuildScriptExecuted { linked_paths } => {
+                    for path in linked_paths {
+                        deps.add_search_path(path);
+                    }
+                }
+                Message::Other => {}
+            }
+        }
+        for (name, mut paths) in candidates {
+            if paths.len() > 1 {
+                let paths = paths
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>();
+                return Err(io::Error::other(format!(
+                    "ambiguous dependency `{}`: cargo built {}",
+                    name,
+                    paths.join(" and ")
+                )));
+            }
+            deps.externs.insert(name, paths.remove(0));
+        }
+        Ok(deps)
+    }
+
+    fn add_search_path(&mut self, path: String) {
+        if !self.search_paths.contains(&path) {
+            self.search_paths.push(path);
+        }
+    }
+
+    /// The `--extern` and `-L` arguments for rustc.
+    pub fn rustc_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for (name, path) in &self.externs {
+            args.push("--extern".to_owned());
+            args.push(format!("{}={}", name, path.display()));
+        }
+        for path in &self.search_paths {
+            args.push("-L".to_owned());
+            args.push(path.clone());
+        }
+        args
+    }
+}
+
+fn is_extern_crate_type(crate_type: &str) -> bool {
+    matches!(crate_type, "lib" | "rlib" | "dylib" | "proc-macro")
+}
+
+fn is_linkable(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("rlib" | "so" | "dylib" | "dll")
+    )
+}
+
+/// Runs cargo with `args` and returns its stdout.
+fn cargo(args: &[&str]) -> io::Result<Vec<u8>> {
+    let cargo = env::var_os("CARGO").unwrap_or_else(|| OsString::from("cargo"));
+    let output = Command::new(cargo)
+        .args(args)
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "cargo {} failed: {}",
+            args[0], output.status
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// The arguments of `cargo build` that affect which dependencies `cargo metadata` resolves.
+fn metadata_args<'a>(args: &[&'a str]) -> Vec<&'a str> {
+    let mut kept = Vec::new();
+    let mut args = args.iter();
+    while let Some(&arg) = args.next() {
+        match arg {
+            "--all-features" | "--no-default-features" => kept.push(arg),
+            "--features" | "-F" | "--manifest-path" => {
+                kept.push(arg);
+                kept.extend(args.next());
+            }
+            _ if arg.starts_with("--features=") || arg.starts_with("--manifest-path=") => {
+                kept.push(arg)
+            }
+            _ => {}
+        }
+    }
+    kept
+}
+
+/// Maps the package ids of the root package of `metadata` and of its direct dependencies and
+/// dev-dependencies to the crate names they are `extern crate`d by.
+fn direct_dependencies(metadata: Metadata) -> io::Result<HashMap<String, String>> {
+    let no_root = || io::Error::other("cargo metadata found no root package");
+    let resolve = metadata.resolve.ok_or_else(no_root)?;
+    let root = resolve.root.ok_or_else(no_root)?;
+    let mut names = HashMap::new();
+    let lib = metadata
+        .packages
+        .iter()
+        .filter(|package| package.id == root)
+        .flat_map(|package| &package.targets)
+        .find(|target| target.crate_types.iter().any(|t| is_extern_crate_type(t)));
+    if let Some(lib) = lib {
+        names.insert(root.clone(), lib.name.replace('-', "_"));
+    }
+    let node = resolve.nodes.into_iter().find(|node| node.id == root);
+    for dep in node.into_iter().flat_map(|node| node.deps) {
+        if dep
+            .dep_kinds
+            .iter()
+            .any(|k| k.kind.as_deref() != Some("build"))
+        {
+            names.insert(dep.pkg, dep.name);
+        }
+    }
+    Ok(names)
+}
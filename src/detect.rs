@@ -0,0 +1,225 @@
+//! Filling in a `Config` from the toolchain installed on this machine, see `Config::detect`.
+
+use std::env;
+use std::error;
+use std::fmt;
+use std::ops;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::common::{Assembly, Codegen, DebugInfoGdb, DebugInfoLldb, RunPassValgrind, Rustdoc};
+use crate::common::{Config, Mode};
+use crate::util;
+
+/// A `Config` with detected toolchain facts, returned by `Config::detect`. Any field can be
+/// changed through `DerefMut` before `build` checks the result.
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+/// Why `ConfigBuilder::build` rejected a `Config`.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// `rustc -vV` failed for this `rustc_path`
+    Rustc(PathBuf),
+    /// `src_base`, or one of `extra_src_bases`, is not a directory
+    SrcBase(PathBuf),
+    /// Tests of `mode` need `tool`, but it was neither found nor set in the `Config` field `field`
+    MissingTool {
+        mode: Mode,
+        tool: &'static str,
+        field: &'static str,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Rustc(ref rustc) => write!(f, "failed to run `{} -vV`", rustc.display()),
+            ConfigError::SrcBase(ref dir) => {
+                write!(f, "test directory {} does not exist", dir.display())
+            }
+            ConfigError::MissingTool { mode, tool, field } => write!(
+                f,
+                "{} tests need {}, which was not found; set `Config::{}`",
+                mode, tool, field
+            ),
+        }
+    }
+}
+
+impl error::Error for ConfigError {}
+
+impl Config {
+    /// Starts a `Config` whose host, target, release channel and LLVM version come from
+    /// `rustc -vV`, and which uses the `FileCheck` (or `$FILECHECK`), `gdb`, `lldb`, `valgrind`
+    /// and `rustdoc` found on `PATH`, and `$CC`, `$CXX`, `$CFLAGS` and `$AR` for run-make tests.
+    ///
+    /// ```no_run
+    /// let config = compiletest_rs::Config::detect()
+    ///     .mode("codegen".parse().unwrap())
+    ///     .src_base("tests/codegen")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn detect() -> ConfigBuilder {
+        let mut builder = ConfigBuilder {
+            config: Config::default(),
+        };
+        builder.detect_rustc();
+        builder.detect_tools();
+        builder
+    }
+}
+
+impl ConfigBuilder {
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.config.mode = mode;
+        self
+    }
+
+    pub fn src_base(mut self, src_base: impl Into<PathBuf>) -> Self {
+        self.config.src_base = src_base.into();
+        self
+    }
+
+    pub fn build_base(mut self, build_base: impl Into<PathBuf>) -> Self {
+        self.config.build_base = build_base.into();
+        self
+    }
+
+    /// Uses another compiler, and detects the facts about it again.
+    pub fn rustc_path(mut self, rustc_path: impl Into<PathBuf>) -> Self {
+        self.config.rustc_path = rustc_path.into();
+        self.detect_rustc();
+        self
+    }
+
+    /// Checks that the compiler runs, that `src_base` and `extra_src_bases` exist and that the
+    /// tools `mode` needs are known.
+    pub fn build(self) -> Result<Config, ConfigError> {
+        let config = self.config;
+        if util::rustc_version_verbose(&config.rustc_path).is_none() {
+            return Err(ConfigError::Rustc(config.rustc_path));
+        }
+        let mut src_bases = Some(&config.src_base)
+            .into_iter()
+            .chain(&config.extra_src_bases);
+        if let Some(src_base) = src_bases.find(|dir| !dir.is_dir()) {
+            return Err(ConfigError::SrcBase(src_base.clone()));
+        }
+
+        let missing = |tool, field| {
+            Err(ConfigError::MissingTool {
+                mode: config.mode,
+                tool,
+                field,
+            })
+        };
+        match config.mode {
+            Codegen | Assembly if config.llvm_filecheck.is_none() => {
+                missing("FileCheck", "llvm_filecheck")
+            }
+            // android has its own gdb handling
+            DebugInfoGdb if config.gdb.is_none() && !config.target.contains("android") => {
+                missing("gdb", "gdb")
+            }
+            DebugInfoLldb if config.lldb_python_dir.is_none() => {
+                missing("LLDB's python module", "lldb_python_dir")
+            }
+            RunPassValgrind if config.force_valgrind && config.valgrind_path.is_none() => {
+                missing("valgrind", "valgrind_path")
+            }
+            Rustdoc if config.rustdoc_path.is_none() => missing("rustdoc", "rustdoc_path"),
+            _ => Ok(config),
+        }
+    }
+
+    fn detect_rustc(&mut self) {
+        let config = &mut self.config;
+        let version = match util::rustc_version_verbose(&config.rustc_path) {
+            Some(version) => version,
+            None => return,
+        };
+        for line in version.lines() {
+            if let Some(host) = line.strip_prefix("host: ") {
+                config.host = host.to_owned();
+                config.target = host.to_owned();
+            } else if let Some(release) = line.strip_prefix("release: ") {
+                config.channel = Some(util::release_channel(release).to_owned());
+            } else if let Some(llvm) = line.strip_prefix("LLVM version: ") {
+                config.llvm_version = Some(llvm.to_owned());
+            }
+        }
+
+        // Prefer the rustdoc of the same toolchain
+        let sibling = config
+            .rustc_path
+            .with_file_name(format!("rustdoc{}", env::consts::EXE_SUFFIX));
+        config.rustdoc_path = if config.rustc_path.parent().is_some_and(|dir| dir.is_dir()) {
+            Some(sibling).filter(|rustdoc| rustdoc.is_file())
+        } else {
+            util::find_on_path("rustdoc")
+        };
+    }
+
+    fn detect_tools(&mut self) {
+        let config = &mut self.config;
+
+        let llvm_major = config
+            .llvm_version
+            .as_ref()
+            .and_then(|version| version.split('.').next());
+        config.llvm_filecheck = env::var_os("FILECHECK")
+            .map(PathBuf::from)
+            .or_else(|| util::find_on_path("FileCheck"))
+            .or_else(|| util::find_on_path(&format!("FileCheck-{}", llvm_major?)));
+
+        if let Some(gdb) = util::find_on_path("gdb") {
+            let version = tool_output(&gdb, &["--version"])
+                .and_then(|out| out.lines().next().and_then(crate::extract_gdb_version));
+            config.gdb = Some(gdb.to_string_lossy().into_owned());
+            config.gdb_version = version;
+            // GDB learned about Rust in 7.12
+            config.gdb_native_rust = version.is_some_and(|v| v >= 7_012_000);
+        }
+
+        if let Some(lldb) = util::find_on_path("lldb") {
+            config.lldb_version = crate::extract_lldb_version(tool_output(&lldb, &["--version"]));
+            config.lldb_python_dir = tool_output(&lldb, &["-P"]).map(|dir| dir.trim().to_owned());
+        }
+
+        config.valgrind_path =
+            util::find_on_path("valgrind").map(|path| path.to_string_lossy().into_owned());
+
+        config.cc = env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+        config.cxx = env::var("CXX").unwrap_or_else(|_| "c++".to_owned());
+        config.cflags = env::var("CFLAGS").unwrap_or_default();
+        config.ar = env::var("AR").unwrap_or_else(|_| "ar".to_owned());
+        config.llvm_components = String::new();
+        config.llvm_cxxflags = String::new();
+    }
+}
+
+impl ops::Deref for ConfigBuilder {
+    type Target = Config;
+
+    fn deref(&self) -> &Self::Target {
+        &self.config
+    }
+}
+
+impl ops::DerefMut for ConfigBuilder {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.config
+    }
+}
+
+/// The stdout of `tool args`, if it ran successfully.
+fn tool_output(tool: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new(tool).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
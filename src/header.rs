@@ -13,9 +13,11 @@ use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use crate::common;
 use crate::common::Config;
+use crate::needs;
 use crate::util;
 
 use crate::extract_gdb_version;
@@ -26,6 +28,12 @@ pub struct EarlyProps {
     pub ignore: bool,
     pub should_fail: bool,
     pub aux: Vec<String>,
+    pub revisions: Vec<String>,
+    /// A `multi-file` header in the `main.rs` or `lib.rs` of a directory makes the directory a
+    /// single test
+    pub multi_file: bool,
+    /// Malformed directives, which fail the test instead of running it
+    pub errors: Vec<String>,
 }
 
 impl EarlyProps {
@@ -34,12 +42,19 @@ impl EarlyProps {
             ignore: false,
             should_fail: false,
             aux: Vec::new(),
+            revisions: Vec::new(),
+            multi_file: false,
+            errors: Vec::new(),
         };
 
         iter_header(testfile, None, config, &mut |ln| {
             props.ignore = props.ignore || config.parse_cfg_name_directive(ln, "ignore");
 
-            if config.has_cfg_prefix(ln, "only") {
+            if let Some(channels) = config.parse_name_value_directive(ln, "only-channel") {
+                props.ignore = props.ignore || !matches_channel(config, &channels);
+            } else if let Some(predicate) = config.parse_name_value_directive(ln, "only-cfg") {
+                props.ignore = props.ignore || !matches_cfg(config, &predicate, &mut props.errors);
+            } else if config.has_cfg_prefix(ln, "only") {
                 props.ignore = match config.parse_cfg_name_directive(ln, "only") {
                     true => props.ignore,
                     false => true,
@@ -49,13 +64,24 @@ impl EarlyProps {
             props.ignore = props.ignore
                 || ignore_gdb(config, ln)
                 || ignore_lldb(config, ln)
-                || ignore_llvm(config, ln);
+                || ignore_llvm(config, ln)
+                || ignore_rustc(config, ln, &mut props.errors)
+                || ignore_feature(config, ln)
+                || needs::is_unmet(config, ln)
+                || config
+                    .parse_name_value_directive(ln, "ignore-cfg")
+                    .is_some_and(|predicate| matches_cfg(config, &predicate, &mut props.errors));
 
             if let Some(s) = config.parse_aux_build(ln) {
                 props.aux.push(s);
             }
 
+            if let Some(r) = config.parse_revisions(ln) {
+                props.revisions.extend(r);
+            }
+
             props.should_fail = props.should_fail || config.parse_name_directive(ln, "should-fail");
+            props.multi_file = props.multi_file || config.parse_name_directive(ln, "multi-file");
         });
 
         return props;
@@ -144,6 +170,113 @@ impl EarlyProps {
             }
         }
 
+        fn ignore_rustc(config: &Config, line: &str, errors: &mut Vec<String>) -> bool {
+            if let Some(channels) = config.parse_name_value_directive(line, "ignore-channel") {
+                return matches_channel(config, &channels);
+            }
+            if let Some(min_version) = config.parse_name_value_directive(line, "min-rustc-version")
+            {
+                let min_version = match parse_rustc_version(&min_version) {
+                    Some(min_version) => min_version,
+                    None => {
+                        errors.push(format!(
+                            "malformed min-rustc-version directive: expected a version like \
+                             `1.78`, found `{}`",
+                            min_version.trim()
+                        ));
+                        return false;
+                    }
+                };
+                // Ignore if the actual version is smaller than the minimum required version. A
+                // prerelease like `1.80.0-nightly` counts as `1.80.0`
+                return util::rustc_release(&config.rustc_path)
+                    .and_then(|release| parse_rustc_version(release.split('-').next().unwrap()))
+                    .is_some_and(|actual_version| actual_version < min_version);
+            }
+            false
+        }
+
+        /// Whether the release channel of the compiler is one of the space-separated `channels`.
+        /// The channel is `config.channel` if set, or else comes from `rustc_path -vV`.
+        fn matches_channel(config: &Config, channels: &str) -> bool {
+            let channel = match config.channel {
+                Some(ref channel) => channel.clone(),
+                None => match util::rustc_release(&config.rustc_path) {
+                    Some(release) => util::release_channel(&release).to_owned(),
+                    None => return false,
+                },
+            };
+            channels.split_whitespace().any(|c| c == channel)
+        }
+
+        /// Whether `predicate`, like `unix` or `target_feature="avx2"`, is set for the target,
+        /// according to `rustc --print cfg`. An empty predicate, or a compiler that can't be run,
+        /// adds to `errors` instead.
+        fn matches_cfg(config: &Config, predicate: &str, errors: &mut Vec<String>) -> bool {
+            let predicate = predicate.trim();
+            if predicate.is_empty() {
+                errors
+                    .push("malformed cfg directive: expected a cfg like `panic=\"abort\"`".into());
+                return false;
+            }
+            let cfg = match util::target_cfg(config) {
+                Some(cfg) => cfg,
+                None => {
+                    errors.push(format!(
+                        "failed to run `{} --print cfg --target {}`",
+                        config.rustc_path.display(),
+                        config.target
+                    ));
+                    return false;
+                }
+            };
+            let (name, value) = parse_cfg(predicate);
+            cfg.iter().any(|line| parse_cfg(line) == (name, value))
+        }
+
+        /// `target_os="linux"` → `("target_os", Some("linux"))`. The quotes are optional.
+        fn parse_cfg(cfg: &str) -> (&str, Option<&str>) {
+            match cfg.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (cfg.trim(), None),
+            }
+        }
+
+        /// `needs-feature: a b` ignores the test unless both features are enabled, and
+        /// `ignore-feature: a b` if either is.
+        fn ignore_feature(config: &Config, line: &str) -> bool {
+            let enabled = |feature: &str| {
+                let feature = feature.replace('-', "_");
+                config
+                    .features
+                    .iter()
+                    .any(|f| f.replace('-', "_").eq_ignore_ascii_case(&feature))
+            };
+            if let Some(features) = config.parse_name_value_directive(line, "needs-feature") {
+                !features.split_whitespace().all(enabled)
+            } else if let Some(features) = config.parse_name_value_directive(line, "ignore-feature")
+            {
+                features.split_whitespace().any(enabled)
+            } else {
+                false
+            }
+        }
+
+        /// `1.78` → `(1, 78, 0)`
+        fn parse_rustc_version(version: &str) -> Option<(u32, u32, u32)> {
+            let mut parts = version.trim().split('.').map(str::parse);
+            let major = parts.next()?.ok()?;
+            let minor = parts.next()?.ok()?;
+            let patch = match parts.next() {
+                Some(patch) => patch.ok()?,
+                None => 0,
+            };
+            match parts.next() {
+                Some(_) => None,
+                None => Some((major, minor, patch)),
+            }
+        }
+
         fn ignore_llvm(config: &Config, line: &str) -> bool {
             if config.system_llvm && line.starts_with("no-system-llvm") {
                 return true;
@@ -234,6 +367,10 @@ pub struct TestProps {
     pub run_rustfix: bool,
     pub rustfix_only_machine_applicable: bool,
     pub assembly_output: Option<String>,
+    // Kill the compiler or test program and fail the test if it runs longer than this
+    pub timeout: Option<Duration>,
+    // Malformed directives, which fail the test when it runs
+    pub errors: Vec<String>,
 }
 
 impl TestProps {
@@ -265,6 +402,8 @@ impl TestProps {
             run_rustfix: false,
             rustfix_only_machine_applicable: false,
             assembly_output: None,
+            timeout: None,
+            errors: vec![],
         }
     }
 
@@ -273,6 +412,7 @@ impl TestProps {
 
         // copy over select properties to the aux build:
         props.incremental_dir = self.incremental_dir.clone();
+        props.timeout = self.timeout;
         props.load_from(testfile, cfg, config);
 
         props
@@ -296,8 +436,12 @@ impl TestProps {
             }
 
             if let Some(flags) = config.parse_compile_flags(ln) {
-                self.compile_flags
-                    .extend(flags.split_whitespace().map(|s| s.to_owned()));
+                match util::split_args(&flags) {
+                    Ok(flags) => self.compile_flags.extend(flags),
+                    Err(e) => self
+                        .errors
+                        .push(format!("malformed compile-flags directive: {}", e)),
+                }
             }
 
             if let Some(edition) = config.parse_edition(ln) {
@@ -377,11 +521,15 @@ impl TestProps {
                 self.run_pass = config.parse_run_pass(ln);
             }
 
-            if let Some(rule) = config.parse_custom_normalization(ln, "normalize-stdout") {
-                self.normalize_stdout.push(rule);
+            match config.parse_custom_normalization(ln, "normalize-stdout") {
+                Some(Ok(rule)) => self.normalize_stdout.push(rule),
+                Some(Err(e)) => self.errors.push(e),
+                None => {}
             }
-            if let Some(rule) = config.parse_custom_normalization(ln, "normalize-stderr") {
-                self.normalize_stderr.push(rule);
+            match config.parse_custom_normalization(ln, "normalize-stderr") {
+                Some(Ok(rule)) => self.normalize_stderr.push(rule),
+                Some(Err(e)) => self.errors.push(e),
+                None => {}
             }
 
             if !self.run_rustfix {
@@ -396,6 +544,12 @@ impl TestProps {
             if self.assembly_output.is_none() {
                 self.assembly_output = config.parse_assembly_output(ln);
             }
+
+            match config.parse_timeout(ln) {
+                Some(Ok(timeout)) => self.timeout = Some(timeout),
+                Some(Err(e)) => self.errors.push(e),
+                None => {}
+            }
         });
 
         for key in &["RUST_TEST_NOCAPTURE", "RUST_TEST_THREADS"] {
@@ -414,12 +568,184 @@ impl TestProps {
 
 const HEADER_PREFIXES: [[&str; 2]; 2] = [["//", "//["], ["//@", "//@["]];
 
-fn iter_header(testfile: &Path, cfg: Option<&str>, config: &Config, it: &mut dyn FnMut(&str)) {
+/// The directives understood by `EarlyProps` and `TestProps`. With `Config::strict_headers`,
+/// `check_directives` rejects any other directive.
+const KNOWN_DIRECTIVES: &[&str] = &[
+    "assembly-output",
+    "aux-build",
+    "build-aux-docs",
+    "check",
+    "check-stdout",
+    "check-test-line-numbers-match",
+    "compile-flags",
+    "edition",
+    "error-pattern",
+    "exec-env",
+    "forbid-output",
+    "force-host",
+    "ignore-cfg",
+    "ignore-channel",
+    "ignore-feature",
+    "ignore-gdb-version",
+    "ignore-test",
+    "min-gdb-version",
+    "min-lldb-version",
+    "min-llvm-version",
+    "min-rustc-version",
+    "min-system-llvm-version",
+    "multi-file",
+    "must-compile-successfully",
+    "needs-feature",
+    "no-prefer-dynamic",
+    "no-system-llvm",
+    "normalize-stderr-test",
+    "normalize-stdout-test",
+    "only-cfg",
+    "only-channel",
+    "pp-exact",
+    "pretty-compare-only",
+    "pretty-expanded",
+    "pretty-mode",
+    "revisions",
+    "run-flags",
+    "run-pass",
+    "run-rustfix",
+    "rustc-env",
+    "rustfix-only-machine-applicable",
+    "should-fail",
+    "timeout",
+];
+
+/// Directives followed by a condition, like `ignore-windows` or `normalize-stderr-32bit`.
+const CONDITIONAL_DIRECTIVES: &[&str] = &["ignore", "only", "normalize-stderr", "normalize-stdout"];
+
+/// The file with the headers of `testfile`: a multi-file test has them in its crate root.
+fn header_file(testfile: &Path) -> Option<PathBuf> {
     if testfile.is_dir() {
-        return;
+        common::test_crate_root(testfile)
+    } else {
+        Some(testfile.to_path_buf())
+    }
+}
+
+/// Checks the `//@` directives of `testfile` when `Config::strict_headers` is set. Fails with a
+/// line per problem if a directive isn't known, suggesting the closest known one, or if it comes
+/// after the first `fn` or `mod`, where `iter_header` stops looking.
+pub fn check_directives(config: &Config, testfile: &Path) -> Result<(), String> {
+    if !config.strict_headers {
+        return Ok(());
+    }
+    let testfile = match header_file(testfile) {
+        Some(file) => file,
+        None => return Ok(()),
+    };
+    let mut errors = Vec::new();
+    let mut after_items = false;
+    let rdr = BufReader::new(File::open(&testfile).unwrap());
+    for (n, ln) in rdr.lines().enumerate() {
+        let ln = ln.unwrap();
+        let ln = ln.trim();
+        if ln.starts_with("fn") || ln.starts_with("mod") {
+            after_items = true;
+            continue;
+        }
+        let directive = match ln.strip_prefix("//@") {
+            Some(directive) => directive,
+            None => continue,
+        };
+        let directive = match directive.strip_prefix('[') {
+            Some(directive) => directive.split_once(']').map_or("", |(_, d)| d),
+            None => directive,
+        };
+        let name = directive
+            .trim_start()
+            .split(&[':', ' '][..])
+            .next()
+            .unwrap();
+        if name.is_empty() {
+            continue;
+        }
+        let location = format!("{}:{}", testfile.display(), n + 1);
+        if !is_known_directive(name) {
+            match closest_directive(name) {
+                Some(known) => errors.push(format!(
+                    "{}: unknown directive `{}`, did you mean `{}`?",
+                    location, name, known
+                )),
+                None => errors.push(format!("{}: unknown directive `{}`", location, name)),
+            }
+        } else if after_items {
+            errors.push(format!(
+                "{}: directive `{}` comes after the first `fn` or `mod`, where it is ignored; \
+                 move it to the top of the file",
+                location, name
+            ));
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+fn is_known_directive(name: &str) -> bool {
+    if KNOWN_DIRECTIVES.contains(&name) || needs::NEEDS.contains(&name) {
+        return true;
+    }
+    if let Some(sanitizer) = name.strip_prefix("needs-sanitizer-") {
+        return needs::SANITIZERS.contains(&sanitizer);
     }
+    CONDITIONAL_DIRECTIVES.iter().any(|directive| {
+        name.strip_prefix(directive)
+            .and_then(|name| name.strip_prefix('-'))
+            .is_some_and(util::is_condition_name)
+    })
+}
+
+/// The known directive closest to `name` by edit distance, if any is close enough to be a typo.
+fn closest_directive(name: &str) -> Option<String> {
+    let sanitizers = needs::SANITIZERS
+        .iter()
+        .map(|sanitizer| format!("needs-sanitizer-{}", sanitizer));
+    let conditions = CONDITIONAL_DIRECTIVES.iter().flat_map(|directive| {
+        util::condition_names().map(move |condition| format!("{}-{}", directive, condition))
+    });
+    KNOWN_DIRECTIVES
+        .iter()
+        .chain(needs::NEEDS)
+        .map(|known| known.to_string())
+        .chain(sanitizers)
+        .chain(conditions)
+        .map(|known| (edit_distance(name, &known), known))
+        .filter(|&(distance, _)| distance <= (name.len() / 3).max(1))
+        .min()
+        .map(|(_, known)| known)
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = diagonal + (ca != cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+fn iter_header(testfile: &Path, cfg: Option<&str>, config: &Config, it: &mut dyn FnMut(&str)) {
+    let testfile = match header_file(testfile) {
+        Some(file) => file,
+        None => return,
+    };
     let header_prefix = HEADER_PREFIXES[config.strict_headers as usize];
-    let rdr = BufReader::new(File::open(testfile).unwrap());
+    let rdr = BufReader::new(File::open(&testfile).unwrap());
     for ln in rdr.lines() {
         // Assume that any directives will be found before the first
         // module or function. This doesn't seem to be an optimization
@@ -523,6 +849,18 @@ impl Config {
             .map(|r| r.trim().to_string())
     }
 
+    fn parse_timeout(&self, line: &str) -> Option<Result<Duration, String>> {
+        self.parse_name_value_directive(line, "timeout").map(|r| {
+            let secs = r.trim();
+            secs.parse().map(Duration::from_secs).map_err(|_| {
+                format!(
+                    "malformed timeout directive: `{}` is not a number of seconds",
+                    secs
+                )
+            })
+        })
+    }
+
     fn parse_env(&self, line: &str, name: &str) -> Option<(String, String)> {
         self.parse_name_value_directive(line, name).map(|nv| {
             // nv is either FOO or FOO=BAR
@@ -549,17 +887,33 @@ impl Config {
         }
     }
 
-    fn parse_custom_normalization(&self, mut line: &str, prefix: &str) -> Option<(String, String)> {
+    /// Parses a normalization rule like `normalize-stderr-test: "from"flags -> "to"`. Fails if the
+    /// regex flags are malformed.
+    fn parse_custom_normalization(
+        &self,
+        mut line: &str,
+        prefix: &str,
+    ) -> Option<Result<(String, String), String>> {
         if self.parse_cfg_name_directive(line, prefix) {
             let from = match parse_normalization_string(&mut line) {
                 Some(s) => s,
                 None => return None,
             };
+            let flags = match parse_regex_flags(&mut line) {
+                Ok(flags) => flags,
+                Err(e) => return Some(Err(e)),
+            };
             let to = match parse_normalization_string(&mut line) {
                 Some(s) => s,
                 None => return None,
             };
-            Some((from, to))
+            let from = unescape_regex(&from);
+            let from = if flags.is_empty() {
+                from
+            } else {
+                format!("(?{}){}", flags, from)
+            };
+            Some(Ok((from, unescape_replacement(&to))))
         } else {
             None
         }
@@ -676,8 +1030,8 @@ fn expand_variables(mut value: String, config: &Config) -> String {
     value
 }
 
-/// Finds the next quoted string `"..."` in `line`, and extract the content from it. Move the `line`
-/// variable after the end of the quoted string.
+/// Finds the next quoted string `"..."` in `line`, and extract the content from it, with escapes
+/// like `\"` left as they are. Move the `line` variable after the end of the quoted string.
 ///
 /// # Examples
 ///
@@ -688,16 +1042,64 @@ fn expand_variables(mut value: String, config: &Config) -> String {
 /// assert_eq!(s, " -> \"something ($WORD bits)\".");
 /// ```
 fn parse_normalization_string(line: &mut &str) -> Option<String> {
-    // FIXME support escapes in strings.
     let begin = match line.find('"') {
         Some(i) => i + 1,
         None => return None,
     };
-    let end = match line[begin..].find('"') {
-        Some(i) => i + begin,
-        None => return None,
-    };
+    let mut escaped = false;
+    let end = line[begin..].find(|c| {
+        let end = c == '"' && !escaped;
+        escaped = c == '\\' && !escaped;
+        end
+    })? + begin;
     let result = line[begin..end].to_owned();
     *line = &line[end + 1..];
     Some(result)
 }
+
+/// Takes the regex flags right after the regex of a normalization rule, like the `m` of
+/// `"^note: .*$"m -> ""`, from `line`.
+fn parse_regex_flags(line: &mut &str) -> Result<String, String> {
+    let end = line
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(line.len());
+    let flags = &line[..end];
+    if let Some(flag) = flags.chars().find(|&c| !"imsxU".contains(c)) {
+        return Err(format!(
+            "malformed normalization rule: unknown regex flag `{}`, expected one of `imsxU`",
+            flag
+        ));
+    }
+    *line = &line[end..];
+    Ok(flags.to_owned())
+}
+
+/// The regex of a normalization rule: `\"` is a quote, and other escapes are the regex's own,
+/// like `\n`, `\\` and `\d`.
+fn unescape_regex(s: &str) -> String {
+    s.replace("\\\"", "\"")
+}
+
+/// The replacement of a normalization rule, with `\"`, `\\`, `\n` and `\t` unescaped. Other
+/// backslashes are kept, and `$1` or `$name` insert what the regex captured.
+fn unescape_replacement(s: &str) -> String {
+    let mut result = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some(c @ ('"' | '\\')) => result.push(c),
+            Some(c) => {
+                result.push('\\');
+                result.push(c);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
@@ -0,0 +1,56 @@
+//! Callbacks around the processes a test spawns, registered in `Config::hooks`.
+//!
+//! ```
+//! use compiletest_rs::hooks::{Hook, HookCx, ProcessKind};
+//! use std::process::Command;
+//! use std::sync::Arc;
+//!
+//! /// Gives each test its own cargo target directory.
+//! struct TargetDir;
+//!
+//! impl Hook for TargetDir {
+//!     fn before(&self, cx: &HookCx, command: &mut Command) {
+//!         if cx.kind == ProcessKind::Run {
+//!             let name = cx.paths.file.file_stem().unwrap();
+//!             command.env("CARGO_TARGET_DIR", cx.config.build_base.join(name));
+//!         }
+//!     }
+//! }
+//!
+//! let mut config = compiletest_rs::Config::default();
+//! config.hooks.push(Arc::new(TargetDir));
+//! ```
+
+use std::process::Command;
+use std::time::Duration;
+
+use crate::common::{Config, TestPaths};
+use crate::runtest::ProcRes;
+
+/// What a process spawned for a test does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProcessKind {
+    /// Running rustc or rustdoc, on the test or one of its `aux-build`s
+    Compile,
+    /// Running the compiled test, or another program such as a debugger or FileCheck
+    Run,
+}
+
+/// The test that a process is spawned for.
+pub struct HookCx<'a> {
+    pub kind: ProcessKind,
+    pub config: &'a Config,
+    pub paths: &'a TestPaths,
+    pub revision: Option<&'a str>,
+}
+
+/// Called before and after every process a test spawns, in the order of `Config::hooks`.
+pub trait Hook: Send + Sync {
+    /// Called before `command` is spawned, e.g. to add arguments or environment variables.
+    fn before(&self, _cx: &HookCx, _command: &mut Command) {}
+
+    /// Called once the process has exited, or was killed for running longer than
+    /// `Config::timeout`, with the time it took. Changes to `proc_res` are seen by the checks
+    /// that follow, e.g. the comparison of stderr with the expected output.
+    fn after(&self, _cx: &HookCx, _proc_res: &mut ProcRes, _duration: Duration) {}
+}
@@ -34,32 +34,79 @@ extern crate diff;
 extern crate filetime;
 extern crate regex;
 extern crate serde_json;
+extern crate toml;
 #[macro_use]
 extern crate serde_derive;
 extern crate rustfix;
 
+use crate::common::{expected_output_paths, UI_EXTENSIONS};
 use crate::common::{DebugInfoGdb, DebugInfoLldb, Pretty};
 use crate::common::{Mode, TestPaths};
+use filetime::FileTime;
+use std::collections::hash_map::DefaultHasher;
 use std::env;
 use std::ffi::OsString;
 use std::fs;
+use std::hash::{Hash, Hasher};
 use std::io;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use self::header::EarlyProps;
+use self::header::{EarlyProps, TestProps};
 
 pub mod common;
+pub mod deps;
+pub mod detect;
 pub mod errors;
 pub mod header;
+pub mod hooks;
 mod json;
+pub mod list;
+mod needs;
+#[cfg(not(feature = "rustc"))]
+mod ordered;
 mod read2;
+pub mod report;
+pub mod runner;
 pub mod runtest;
+pub mod shard;
+pub mod suites;
+pub mod summary;
 pub mod uidiff;
 pub mod util;
+pub mod watch;
 
 pub use crate::common::Config;
+pub use crate::list::list_tests;
+pub use crate::summary::{RunError, TestOutcome, TestStatus, TestSummary};
+pub use crate::watch::watch;
 
 pub fn run_tests(config: &Config) {
+    match run_tests_with_summary(config) {
+        Ok(summary) => {
+            if !summary.is_success() {
+                panic!("Some tests failed");
+            }
+        }
+        Err(e @ RunError::Collect(_)) => panic!("{}", e),
+        Err(e) => {
+            println!("{}", e);
+        }
+    }
+}
+
+/// Like `run_tests`, but instead of panicking when a test fails, returns the outcome of every
+/// test that was run or ignored.
+pub fn run_tests_with_summary(config: &Config) -> Result<TestSummary, RunError> {
+    if let Some(format) = config.list {
+        let tests = list::listed_tests(config).map_err(RunError::Collect)?;
+        list::write_list(&tests, format, &mut io::stdout())?;
+        return Ok(TestSummary::default());
+    }
+
     if config.target.contains("android") {
         if let DebugInfoGdb = config.mode {
             println!(
@@ -99,7 +146,12 @@ pub fn run_tests(config: &Config) {
         }
     }
     let opts = test_opts(config);
-    let tests = make_tests(config);
+    let outcomes = Arc::new(Mutex::new(Vec::new()));
+    let tests = collect_tests(config)
+        .map_err(RunError::Collect)?
+        .into_iter()
+        .map(|test| record_outcome(config, test, &outcomes))
+        .collect::<Vec<_>>();
     // sadly osx needs some file descriptor limits raised for running tests in
     // parallel (especially when we have lots and lots of child processes).
     // For context, see #8904
@@ -107,14 +159,92 @@ pub fn run_tests(config: &Config) {
     // Prevent issue #21352 UAC blocking .exe containing 'patch' etc. on Windows
     // If #11207 is resolved (adding manifest to .exe) this becomes unnecessary
     env::set_var("__COMPAT_LAYER", "RunAsInvoker");
-    let res = test::run_tests_console(&opts, tests.into_iter().collect());
-    match res {
-        Ok(true) => {}
-        Ok(false) => panic!("Some tests failed"),
-        Err(e) => {
-            println!("I/O failure during tests: {:?}", e);
+    let start = Instant::now();
+    #[cfg(not(feature = "rustc"))]
+    let tests = ordered::prepare(config, &opts, tests);
+    test::run_tests_console(&opts, tests)?;
+
+    let mut outcomes = mem::take(&mut *outcomes.lock().unwrap());
+    outcomes.sort_by(|a, b| a.name.cmp(&b.name));
+    let summary = TestSummary {
+        outcomes,
+        duration: start.elapsed(),
+    };
+
+    if config.write_report {
+        report::write_reports(config, &summary)?;
+    }
+    // Recorded by every run, so that a run with `rerun_failed` can pick up the failures of the
+    // run before it
+    if config.build_base.is_dir() {
+        if let Err(e) = report::update_failed_tests(config, &summary) {
+            eprintln!(
+                "warning: failed to update {}: {}",
+                report::failed_tests_path(config).display(),
+                e
+            );
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Wraps the test function of `test` so that its outcome is pushed onto `outcomes` once it has
+/// run. Tests that will be skipped as ignored are recorded right away.
+fn record_outcome(
+    config: &Config,
+    test: test::TestDescAndFn,
+    outcomes: &Arc<Mutex<Vec<TestOutcome>>>,
+) -> test::TestDescAndFn {
+    let test::TestDescAndFn { desc, testfn } = test;
+    let name = desc.name.to_string();
+    let mode = config.mode;
+
+    if desc.ignore && !config.run_ignored {
+        if matches_filters(config, &name) {
+            outcomes
+                .lock()
+                .unwrap()
+                .push(TestOutcome::ignored(name, mode));
         }
+        return test::TestDescAndFn { desc, testfn };
     }
+
+    let f = match testfn {
+        test::DynTestFn(f) => f,
+        testfn => return test::TestDescAndFn { desc, testfn },
+    };
+    let should_panic = desc.should_panic != test::ShouldPanic::No;
+    let outcomes = Arc::clone(outcomes);
+    let testfn = test::DynTestFn(Box::new(move || {
+        let start = Instant::now();
+        let result = panic::catch_unwind(AssertUnwindSafe(f));
+        let outcome = TestOutcome::finished(
+            name,
+            mode,
+            start.elapsed(),
+            should_panic,
+            result.as_ref().err().map(|payload| &**payload),
+        );
+        outcomes.lock().unwrap().push(outcome);
+        match result {
+            Ok(res) => res,
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    }));
+    test::TestDescAndFn { desc, testfn }
+}
+
+/// Whether the test harness will consider the test `name` given the filters in `config`.
+fn matches_filters(config: &Config, name: &str) -> bool {
+    config.filters.is_empty()
+        || config.filters.iter().any(|filter| {
+            if config.filter_exact {
+                name == filter
+            } else {
+                name.contains(filter.as_str())
+            }
+        })
 }
 
 pub fn test_opts(config: &Config) -> test::TestOpts {
@@ -141,21 +271,32 @@ pub fn test_opts(config: &Config) -> test::TestOpts {
             Err(_) => false,
         },
         color: test::AutoColor,
-        test_threads: None,
+        test_threads: config.test_threads,
         skip: vec![],
         list: false,
         options: test::Options::new(),
         time_options: None,
         #[cfg(feature = "rustc")]
-        shuffle: false,
+        shuffle: config.shuffle_seed.is_some(),
         #[cfg(feature = "rustc")]
-        shuffle_seed: None,
+        shuffle_seed: config.shuffle_seed,
         #[cfg(feature = "rustc")]
-        fail_fast: false,
+        fail_fast: config.fail_fast,
     }
 }
 
 pub fn make_tests(config: &Config) -> Vec<test::TestDescAndFn> {
+    collect_tests(config).unwrap()
+}
+
+fn collect_tests(config: &Config) -> io::Result<Vec<test::TestDescAndFn>> {
+    Ok(collect_test_paths(config)?
+        .iter()
+        .map(|paths| make_test(config, paths))
+        .collect())
+}
+
+fn collect_test_paths(config: &Config) -> io::Result<Vec<TestPaths>> {
     debug!("making tests from {:?}", config.src_base.display());
     let mut tests = Vec::new();
     collect_tests_from_dir(
@@ -164,9 +305,41 @@ pub fn make_tests(config: &Config) -> Vec<test::TestDescAndFn> {
         &config.src_base,
         &PathBuf::new(),
         &mut tests,
-    )
-    .unwrap();
-    tests
+    )?;
+
+    let mut names = vec![config.src_base.file_name()];
+    for src_base in &config.extra_src_bases {
+        debug!("making tests from {:?}", src_base.display());
+        let name = src_base.file_name();
+        if names.contains(&name) || name.is_none_or(|name| config.src_base.join(name).exists()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the tests in {} would have the same names as other tests",
+                    src_base.display()
+                ),
+            ));
+        }
+        names.push(name);
+        let relative_dir = PathBuf::from(name.unwrap());
+        collect_tests_from_dir(config, src_base, src_base, &relative_dir, &mut tests)?;
+    }
+
+    if config.rerun_failed {
+        if let Some(failed) = report::read_failed_tests(config)? {
+            tests.retain(|paths| failed.contains(&make_test_name(config, paths).to_string()));
+        }
+    }
+
+    if config.shard_count != 1 || !config.shard_weights.is_empty() {
+        let names = tests
+            .iter()
+            .map(|paths| make_test_name(config, paths).to_string())
+            .collect::<Vec<_>>();
+        let mut in_shard = shard::select(config, &names)?.into_iter();
+        tests.retain(|_| in_shard.next().unwrap());
+    }
+    Ok(tests)
 }
 
 fn collect_tests_from_dir(
@@ -174,7 +347,7 @@ fn collect_tests_from_dir(
     base: &Path,
     dir: &Path,
     relative_dir_path: &Path,
-    tests: &mut Vec<test::TestDescAndFn>,
+    tests: &mut Vec<TestPaths>,
 ) -> io::Result<()> {
     // Ignore directories that contain a file
     // `compiletest-ignore-dir`.
@@ -190,7 +363,7 @@ fn collect_tests_from_dir(
                 base: base.to_path_buf(),
                 relative_dir: relative_dir_path.parent().unwrap().to_path_buf(),
             };
-            tests.push(make_test(config, &paths));
+            tests.push(paths);
             return Ok(());
         }
     }
@@ -211,7 +384,11 @@ fn collect_tests_from_dir(
         let file = file?;
         let file_path = file.path();
         let file_name = file.file_name();
-        if is_test(&file_name) {
+        let is_test_file = match config.mode {
+            Mode::Custom(mode) => runner::runner(mode).is_test(&file_name),
+            _ => is_test(&file_name),
+        };
+        if is_test_file || is_multi_file_test(config, &file_path) {
             debug!("found test file: {:?}", file_path.display());
             // output directory `$build/foo` so we can write
             // `$build/foo/bar` into it. We do this *now* in this
@@ -226,7 +403,7 @@ fn collect_tests_from_dir(
                 base: base.to_path_buf(),
                 relative_dir: relative_dir_path.to_path_buf(),
             };
-            tests.push(make_test(config, &paths))
+            tests.push(paths)
         } else if file_path.is_dir() {
             let relative_file_path = relative_dir_path.join(file.file_name());
             if &file_name == "auxiliary" {
@@ -248,6 +425,15 @@ fn collect_tests_from_dir(
     Ok(())
 }
 
+/// Whether `dir` is a test crate made of several files: a directory with a `main.rs` or `lib.rs`
+/// that has a `multi-file` header, or that is next to a `compiletest.toml`.
+fn is_multi_file_test(config: &Config, dir: &Path) -> bool {
+    if config.mode == Mode::RunMake || common::test_crate_root(dir).is_none() {
+        return false;
+    }
+    dir.join(suites::CONFIG_FILE).is_file() || EarlyProps::from_file(config, dir).multi_file
+}
+
 pub fn is_test(file_name: &OsString) -> bool {
     let file_name = file_name.to_str().unwrap();
 
@@ -262,11 +448,13 @@ pub fn is_test(file_name: &OsString) -> bool {
 
 pub fn make_test(config: &Config, testpaths: &TestPaths) -> test::TestDescAndFn {
     let early_props = EarlyProps::from_file(config, &testpaths.file);
+    let invalid_directives = invalid_directives(config, testpaths, &early_props);
 
     // The `should-fail` annotation doesn't apply to pretty tests,
     // since we run the pretty printer across all tests by default.
     // If desired, we could add a `should-fail-pretty` annotation.
     let should_panic = match config.mode {
+        _ if invalid_directives.is_some() => test::ShouldPanic::No,
         Pretty => test::ShouldPanic::No,
         _ => {
             if early_props.should_fail {
@@ -280,7 +468,7 @@ pub fn make_test(config: &Config, testpaths: &TestPaths) -> test::TestDescAndFn
     test::TestDescAndFn {
         desc: test::TestDesc {
             name: make_test_name(config, testpaths),
-            ignore: early_props.ignore,
+            ignore: invalid_directives.is_none() && is_ignored(config, testpaths, &early_props),
             should_panic: should_panic,
             #[cfg(not(feature = "rustc"))]
             allow_fail: false,
@@ -302,10 +490,38 @@ pub fn make_test(config: &Config, testpaths: &TestPaths) -> test::TestDescAndFn
             #[cfg(feature = "rustc")]
             end_col: 0,
         },
-        testfn: make_test_closure(config, testpaths),
+        testfn: match invalid_directives {
+            Some(errors) => invalid_directives_closure(errors),
+            None => make_test_closure(config, testpaths),
+        },
     }
 }
 
+/// The malformed and unknown directives of the test, if any. Checked when the tests are collected
+/// rather than when they run, so that ignored tests are checked too.
+fn invalid_directives(
+    config: &Config,
+    testpaths: &TestPaths,
+    early_props: &EarlyProps,
+) -> Option<String> {
+    let mut errors = Vec::new();
+    if let Err(unknown) = header::check_directives(config, &testpaths.file) {
+        errors.push(unknown);
+    }
+    for error in &early_props.errors {
+        errors.push(format!("{}: {}", testpaths.file.display(), error));
+    }
+    if errors.is_empty() {
+        None
+    } else {
+        Some(errors.join("\n"))
+    }
+}
+
+fn is_ignored(config: &Config, testpaths: &TestPaths, early_props: &EarlyProps) -> bool {
+    early_props.ignore || (config.skip_up_to_date && is_up_to_date(config, testpaths))
+}
+
 fn stamp(config: &Config, testpaths: &TestPaths) -> PathBuf {
     let stamp_name = format!(
         "{}-{}.stamp",
@@ -316,14 +532,143 @@ fn stamp(config: &Config, testpaths: &TestPaths) -> PathBuf {
         .build_base
         .canonicalize()
         .unwrap_or_else(|_| config.build_base.clone())
+        .join(&testpaths.relative_dir)
         .join(stamp_name)
 }
 
+/// Hashes everything in `config` that affects the outcome of a test, along with the version of
+/// the compiler, so that a stamp written with a different setup is considered stale.
+fn compute_stamp_hash(config: &Config) -> String {
+    let mut hash = DefaultHasher::new();
+    config.mode.to_string().hash(&mut hash);
+    config.stage_id.hash(&mut hash);
+    config.rustc_path.hash(&mut hash);
+    util::rustc_version_verbose(&config.rustc_path).hash(&mut hash);
+    config.target.hash(&mut hash);
+    config.host.hash(&mut hash);
+    config.target_rustcflags.hash(&mut hash);
+    config.target_rustc_args.hash(&mut hash);
+    config.host_rustcflags.hash(&mut hash);
+    config.dependencies.hash(&mut hash);
+    config.features.hash(&mut hash);
+    config.normalize_stdout.hash(&mut hash);
+    config.normalize_stderr.hash(&mut hash);
+    config.normalize_presets.hash(&mut hash);
+    config.runtool.hash(&mut hash);
+    config.linker.hash(&mut hash);
+    config.edition.hash(&mut hash);
+    config.strict_headers.hash(&mut hash);
+    config.compile_test_exit_code.hash(&mut hash);
+    format!("{:x}", hash.finish())
+}
+
+/// Whether the stamp of a previous successful run of this test was written with the same setup
+/// and is newer than all of the test's inputs.
+fn is_up_to_date(config: &Config, testpaths: &TestPaths) -> bool {
+    let stamp_name = stamp(config, testpaths);
+    match fs::read_to_string(&stamp_name) {
+        Ok(contents) if contents == compute_stamp_hash(config) => {}
+        _ => return false,
+    }
+
+    let mut inputs = test_inputs(config, testpaths);
+    inputs.push(config.rustc_path.clone());
+    inputs.extend(config.dependencies.externs.values().cloned());
+    inputs.extend(config.linked_libraries());
+
+    let stamp_time = mtime(&stamp_name);
+    inputs.iter().all(|input| mtime(input) < stamp_time)
+}
+
+/// The files that a test is made of: the test file (or all files of a multi-file test), the
+/// files of its `aux-build`s and theirs, in any revision, its expected output files and the
+/// `compiletest-defaults.toml` files that apply to it, whether they exist or not.
+fn test_inputs(config: &Config, testpaths: &TestPaths) -> Vec<PathBuf> {
+    let mut inputs = vec![testpaths.file.clone()];
+    if testpaths.file.is_dir() {
+        collect_files(&testpaths.file, &mut inputs);
+    }
+    inputs.extend(suites::defaults_files(testpaths));
+
+    let runner = match config.mode {
+        Mode::Custom(mode) => Some(runner::runner(mode)),
+        _ => None,
+    };
+    let kinds = runner
+        .as_ref()
+        .map_or(UI_EXTENSIONS, |runner| runner.output_kinds());
+    let base_props = TestProps::from_file(&testpaths.file, None, config);
+    let revisions = base_props.revisions.iter().map(|r| Some(r.as_str()));
+    for revision in Some(None).into_iter().chain(revisions) {
+        let props = match revision {
+            Some(revision) => TestProps::from_file(&testpaths.file, Some(revision), config),
+            None => base_props.clone(),
+        };
+        push_aux_files(config, testpaths, &props, revision, &mut inputs);
+        for kind in kinds {
+            inputs.extend(expected_output_paths(config, testpaths, revision, kind));
+        }
+    }
+    inputs.sort();
+    inputs.dedup();
+    inputs
+}
+
+/// Pushes the files of the `aux-build`s in `props`, the properties of the test or auxiliary crate
+/// at `paths`, and of their own `aux-build`s, like `TestCx::build_all_auxiliary` builds them.
+fn push_aux_files(
+    config: &Config,
+    paths: &TestPaths,
+    props: &TestProps,
+    revision: Option<&str>,
+    inputs: &mut Vec<PathBuf>,
+) {
+    for aux in &props.aux_builds {
+        let file = common::aux_file_path(config, paths, aux);
+        if inputs.contains(&file) {
+            continue;
+        }
+        inputs.push(file.clone());
+        let aux_props = props.from_aux_file(&file, revision, config);
+        let aux_paths = TestPaths {
+            file,
+            ..paths.clone()
+        };
+        push_aux_files(config, &aux_paths, &aux_props, revision, inputs);
+    }
+}
+
+/// Pushes the paths of the files in `dir` and its subdirectories.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+/// The modification time of `path`, or the earliest possible time if it does not exist.
+fn mtime(path: &Path) -> FileTime {
+    fs::metadata(path)
+        .map(|metadata| FileTime::from_last_modification_time(&metadata))
+        .unwrap_or_else(|_| FileTime::zero())
+}
+
 pub fn make_test_name(config: &Config, testpaths: &TestPaths) -> test::TestName {
     // Convert a complete path to something like
     //
     //    run-pass/foo/bar/baz.rs
-    let path = PathBuf::from(config.src_base.file_name().unwrap())
+    //
+    // Tests of `extra_src_bases` already have the name of their directory in `relative_dir`.
+    let base_name = if testpaths.base == config.src_base {
+        PathBuf::from(config.src_base.file_name().unwrap())
+    } else {
+        PathBuf::new()
+    };
+    let path = base_name
         .join(&testpaths.relative_dir)
         .join(&testpaths.file.file_name().unwrap());
     test::DynTestName(format!("[{}] {}", config.mode, path.display()))
@@ -340,6 +685,15 @@ pub fn make_test_closure(config: &Config, testpaths: &TestPaths) -> test::TestFn
     }))
 }
 
+/// A test that fails with `errors`, the invalid directives found in its file.
+fn invalid_directives_closure(errors: String) -> test::TestFn {
+    fn fail<T>(errors: &str) -> T {
+        panic!("invalid directives:\n{}", errors);
+    }
+
+    test::DynTestFn(Box::new(move || fail(&errors)))
+}
+
 fn extract_gdb_version(full_version_line: &str) -> Option<u32> {
     let full_version_line = full_version_line.trim();
 
@@ -410,23 +764,35 @@ fn extract_gdb_version(full_version_line: &str) -> Option<u32> {
     None
 }
 
-#[allow(dead_code)]
 fn extract_lldb_version(full_version_line: Option<String>) -> Option<String> {
     // Extract the major LLDB version from the given version string.
     // LLDB version strings are different for Apple and non-Apple platforms.
-    // At the moment, this function only supports the Apple variant, which looks
-    // like this:
+    // The Apple variant looks like this:
     //
     // LLDB-179.5 (older versions)
     // lldb-300.2.51 (new versions)
     //
+    // and the upstream LLVM one like this:
+    //
+    // lldb version 17.0.6
+    //
     // We are only interested in the major version number, so this function
-    // will return `Some("179")` and `Some("300")` respectively.
+    // will return `Some("179")`, `Some("300")` and `Some("17")` respectively.
 
     if let Some(ref full_version_line) = full_version_line {
         if !full_version_line.trim().is_empty() {
             let full_version_line = full_version_line.trim();
 
+            if let Some(version) = full_version_line.strip_prefix("lldb version ") {
+                let major = version
+                    .chars()
+                    .take_while(|c| c.is_ascii_digit())
+                    .collect::<String>();
+                if !major.is_empty() {
+                    return Some(major);
+                }
+            }
+
             for (pos, l) in full_version_line.char_indices() {
                 if l != 'l' && l != 'L' {
                     continue;
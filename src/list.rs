@@ -0,0 +1,103 @@
+//! Enumerating the tests a `Config` would run, without compiling anything.
+
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::common::{Config, Mode};
+use crate::header::EarlyProps;
+
+/// The output format of `list_tests`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ListFormat {
+    /// One line per test, followed by the number of tests
+    Text,
+    /// One JSON object per test and line
+    Json,
+}
+
+impl FromStr for ListFormat {
+    type Err = ();
+    fn from_str(s: &str) -> Result<ListFormat, ()> {
+        match s {
+            "text" => Ok(ListFormat::Text),
+            "json" => Ok(ListFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A test that `run_tests` would pick up.
+#[derive(Clone, Debug, Serialize)]
+pub struct ListedTest {
+    /// The name given to the test by `make_test_name`, e.g. `[ui] ui/foo.rs`
+    pub name: String,
+    #[serde(serialize_with = "serialize_mode")]
+    pub mode: Mode,
+    pub revisions: Vec<String>,
+    /// Whether the test would be skipped, e.g. because of an `ignore-` or `only-` header
+    pub ignored: bool,
+}
+
+fn serialize_mode<S: serde::Serializer>(mode: &Mode, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_str(mode)
+}
+
+/// Collects the tests matching `config.filters`, sorted by name.
+pub fn listed_tests(config: &Config) -> io::Result<Vec<ListedTest>> {
+    let mut tests = Vec::new();
+    for paths in crate::collect_test_paths(config)? {
+        let name = crate::make_test_name(config, &paths).to_string();
+        if !crate::matches_filters(config, &name) {
+            continue;
+        }
+        let early_props = EarlyProps::from_file(config, &paths.file);
+        // Like `make_test`, which runs tests with invalid directives to report them
+        let invalid_directives = crate::invalid_directives(config, &paths, &early_props).is_some();
+        tests.push(ListedTest {
+            name,
+            mode: config.mode,
+            ignored: !invalid_directives && crate::is_ignored(config, &paths, &early_props),
+            revisions: early_props.revisions,
+        });
+    }
+    tests.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(tests)
+}
+
+/// Prints the tests `run_tests` would run with `config` to stdout, in the `config.list` format
+/// (text if unset).
+pub fn list_tests(config: &Config) -> io::Result<()> {
+    let tests = listed_tests(config)?;
+    let format = config.list.unwrap_or(ListFormat::Text);
+    write_list(&tests, format, &mut io::stdout())
+}
+
+/// Writes `tests` in `format`. Text lines look like `[ui] ui/foo.rs (ui) [revisions: a, b]
+/// ignored`; JSON lines like `{"name":"[ui] ui/foo.rs","mode":"ui","revisions":["a","b"],
+/// "ignored":true}`.
+pub fn write_list(tests: &[ListedTest], format: ListFormat, w: &mut dyn Write) -> io::Result<()> {
+    match format {
+        ListFormat::Text => {
+            for test in tests {
+                write!(w, "{} ({})", test.name, test.mode)?;
+                if !test.revisions.is_empty() {
+                    write!(w, " [revisions: {}]", test.revisions.join(", "))?;
+                }
+                if test.ignored {
+                    write!(w, " ignored")?;
+                }
+                writeln!(w)?;
+            }
+            let ignored = tests.iter().filter(|test| test.ignored).count();
+            writeln!(w, "\n{} tests, {} ignored", tests.len(), ignored)
+        }
+        ListFormat::Json => {
+            for test in tests {
+                serde_json::to_writer(&mut *w, test)?;
+                writeln!(w)?;
+            }
+            Ok(())
+        }
+    }
+}
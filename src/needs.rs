@@ -0,0 +1,150 @@
+//! The `needs-*` headers, which ignore a test unless the toolchain and target support something,
+//! e.g. `needs-unwind` or `needs-sanitizer-address`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+
+use crate::common::Config;
+use crate::util;
+
+/// The names of the `needs-*` headers handled by `is_unmet`, besides `needs-sanitizer-<name>`.
+pub(crate) const NEEDS: &[&str] = &[
+    "needs-asm-support",
+    "needs-dynamic-linking",
+    "needs-proc-macro",
+    "needs-run-enabled",
+    "needs-unwind",
+];
+
+/// The sanitizers of `needs-sanitizer-<name>`, as named in target specs.
+pub(crate) const SANITIZERS: &[&str] = &[
+    "address",
+    "cfi",
+    "dataflow",
+    "hwaddress",
+    "kcfi",
+    "kernel-address",
+    "leak",
+    "memory",
+    "memtag",
+    "safestack",
+    "shadow-call-stack",
+    "thread",
+];
+
+/// The architectures on which `asm!` is stable.
+const ASM_SUPPORTED_ARCHS: &[&str] = &[
+    "aarch64",
+    "arm",
+    "arm64ec",
+    "loongarch64",
+    "riscv32",
+    "riscv64",
+    "s390x",
+    "x86",
+    "x86_64",
+];
+
+/// What `rustc --print target-spec-json` says about a target.
+#[derive(Clone, Debug)]
+struct TargetSpec {
+    /// Whether the target can link dylibs at all; `crt-static` can still prevent it
+    dynamic_linking: bool,
+    supported_sanitizers: Vec<String>,
+}
+
+/// Whether `line` is a `needs-*` header whose requirement isn't met by `config`, which means the
+/// test is ignored.
+pub(crate) fn is_unmet(config: &Config, line: &str) -> bool {
+    let name = line.split(&[':', ' '][..]).next().unwrap();
+    match name {
+        "needs-asm-support" => !has_cfg(config, "target_arch", ASM_SUPPORTED_ARCHS),
+        "needs-dynamic-linking" => !dynamic_linking(config),
+        "needs-proc-macro" => !proc_macro(config),
+        "needs-run-enabled" => !run_enabled(config),
+        "needs-unwind" => !has_cfg(config, "panic", &["unwind"]),
+        _ => match name.strip_prefix("needs-sanitizer-") {
+            Some(sanitizer) => !target_spec(config, &config.target)
+                .supported_sanitizers
+                .iter()
+                .any(|s| s == sanitizer),
+            None => false,
+        },
+    }
+}
+
+/// Whether `rustc --print cfg` has `name="value"` for one of `values`.
+fn has_cfg(config: &Config, name: &str, values: &[&str]) -> bool {
+    let cfg = util::target_cfg(config).unwrap_or_default();
+    values
+        .iter()
+        .any(|value| cfg.contains(&format!("{}=\"{}\"", name, value)))
+}
+
+/// Whether the target supports dylibs, which statically linked C runtimes, like musl's by
+/// default, don't.
+fn dynamic_linking(config: &Config) -> bool {
+    target_spec(config, &config.target).dynamic_linking
+        && !has_cfg(config, "target_feature", &["crt-static"])
+}
+
+/// Whether proc macros can be built and loaded: they are dylibs built for the host.
+fn proc_macro(config: &Config) -> bool {
+    target_spec(config, &config.host).dynamic_linking
+}
+
+/// Whether test programs built for the target can run here: natively, or through
+/// `runtool`, `remote_test_client` or, for wasm, `nodejs`.
+fn run_enabled(config: &Config) -> bool {
+    config.target == config.host
+        || config.runtool.is_some()
+        || config.remote_test_client.is_some()
+        || ((config.target.contains("wasm32") || config.target.contains("emscripten"))
+            && config.nodejs.is_some())
+}
+
+/// The spec of `target`, asked once per compiler and target for the lifetime of the process. If
+/// the compiler won't print it, dynamic linking is assumed except on the targets known not to
+/// support it, and no sanitizers.
+fn target_spec(config: &Config, target: &str) -> TargetSpec {
+    static SPECS: OnceLock<Mutex<HashMap<(PathBuf, String), TargetSpec>>> = OnceLock::new();
+
+    let mut specs = SPECS.get_or_init(Default::default).lock().unwrap();
+    specs
+        .entry((config.rustc_path.clone(), target.to_owned()))
+        .or_insert_with(|| {
+            print_target_spec(config, target).unwrap_or_else(|| TargetSpec {
+                dynamic_linking: !["musl", "wasm32", "emscripten"]
+                    .iter()
+                    .any(|t| target.contains(t)),
+                supported_sanitizers: vec![],
+            })
+        })
+        .clone()
+}
+
+fn print_target_spec(config: &Config, target: &str) -> Option<TargetSpec> {
+    // Printing the spec is unstable, even though the spec of a built-in target is not
+    let output = Command::new(&config.rustc_path)
+        .args(["-Z", "unstable-options", "--print", "target-spec-json"])
+        .args(["--target", target])
+        .env("RUSTC_BOOTSTRAP", "1")
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let spec = serde_json::from_slice::<serde_json::Value>(&output.stdout).ok()?;
+    let sanitizers = spec["supported-sanitizers"].as_array();
+    Some(TargetSpec {
+        dynamic_linking: spec["dynamic-linking"].as_bool().unwrap_or(false)
+            && !spec["only-cdylib"].as_bool().unwrap_or(false),
+        supported_sanitizers: sanitizers
+            .into_iter()
+            .flatten()
+            .filter_map(|s| s.as_str().map(str::to_owned))
+            .collect(),
+    })
+}
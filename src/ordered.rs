@@ -0,0 +1,168 @@
+//! `Config::fail_fast` and `Config::shuffle_seed` for the `tester` backend, which has neither and
+//! always starts the tests in the order of their names. The test functions handed to
+//! `test::run_tests_console` are replaced by slots that take the next test from a shared schedule
+//! and run it, until the test of the slot itself has run; the slot then reports its result.
+//! libtest supports both natively, so the `rustc` backend doesn't need this.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+use crate::common::Config;
+use crate::test;
+
+type TestFn = Box<dyn FnOnce() + Send>;
+
+/// The tests that the harness will run, in the order to start them in.
+struct Schedule {
+    state: Mutex<State>,
+    /// Notified whenever a test has finished
+    finished: Condvar,
+    fail_fast: bool,
+}
+
+struct State {
+    /// The test functions that haven't started yet, and whether they should panic
+    tests: Vec<(Option<TestFn>, bool)>,
+    /// The order to start the tests in, as indices into `tests`
+    order: VecDeque<usize>,
+    /// The results of the tests that ran, until the slot of the test takes them
+    results: HashMap<usize, thread::Result<()>>,
+    /// Set when a test fails with `fail_fast`, after which no test is started
+    stopped: bool,
+}
+
+/// Applies `config.fail_fast` and `config.shuffle_seed` to the `tests` that the harness will run
+/// with `opts`.
+pub(crate) fn prepare(
+    config: &Config,
+    opts: &test::TestOpts,
+    mut tests: Vec<test::TestDescAndFn>,
+) -> Vec<test::TestDescAndFn> {
+    if !config.fail_fast && config.shuffle_seed.is_none() {
+        return tests;
+    }
+
+    let will_run = will_run(opts, &tests);
+    let schedule = Arc::new(Schedule {
+        state: Mutex::new(State {
+            tests: Vec::new(),
+            order: VecDeque::new(),
+            results: HashMap::new(),
+            stopped: false,
+        }),
+        finished: Condvar::new(),
+        fail_fast: config.fail_fast,
+    });
+    let mut names = Vec::new();
+    {
+        let mut state = schedule.state.lock().unwrap();
+        for test in &mut tests {
+            let name = test.desc.name.to_string();
+            if !will_run.contains(&name) {
+                continue;
+            }
+            let placeholder = test::DynTestFn(Box::new(|| {}));
+            let f: TestFn = match mem::replace(&mut test.testfn, placeholder) {
+                test::DynTestFn(f) => f,
+                test::StaticTestFn(f) => Box::new(f),
+                _ => unreachable!("only tests are scheduled"),
+            };
+            let index = state.tests.len();
+            let should_panic = test.desc.should_panic != test::ShouldPanic::No;
+            state.tests.push((Some(f), should_panic));
+            names.push(name);
+            let schedule = Arc::clone(&schedule);
+            test.testfn = test::DynTestFn(Box::new(move || run_slot(&schedule, index)));
+        }
+
+        let mut order = (0..names.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| names[a].cmp(&names[b]));
+        if let Some(seed) = config.shuffle_seed {
+            shuffle(&mut order, seed);
+        }
+        state.order = order.into();
+    }
+    tests
+}
+
+/// The names of the tests that the harness will run rather than filter out or ignore.
+fn will_run(opts: &test::TestOpts, tests: &[test::TestDescAndFn]) -> HashSet<String> {
+    if !opts.run_tests {
+        return HashSet::new();
+    }
+    let placeholders = tests
+        .iter()
+        .filter(|test| matches!(test.testfn, test::DynTestFn(_) | test::StaticTestFn(_)))
+        .map(|test| test::TestDescAndFn {
+            desc: test.desc.clone(),
+            testfn: test::DynTestFn(Box::new(|| {})),
+        })
+        .collect();
+    test::filter_tests(opts, placeholders)
+        .into_iter()
+        .filter(|test| !test.desc.ignore)
+        .map(|test| test.desc.name.to_string())
+        .collect()
+}
+
+/// The test function of the slot for test `index`: runs tests from `schedule` until that test
+/// has finished, and then returns or panics as it did. Fails without running the test if
+/// `fail_fast` stopped the run before it started.
+fn run_slot(schedule: &Schedule, index: usize) {
+    let mut state = schedule.state.lock().unwrap();
+    loop {
+        if let Some(result) = state.results.remove(&index) {
+            drop(state);
+            if let Err(payload) = result {
+                panic::resume_unwind(payload);
+            }
+            return;
+        }
+        if state.stopped && state.tests[index].0.take().is_some() {
+            drop(state);
+            panic!("not run: an earlier test failed with `fail_fast` set");
+        }
+
+        let mut next = None;
+        while next.is_none() && !state.stopped {
+            match state.order.pop_front() {
+                Some(i) => next = state.tests[i].0.take().map(|f| (i, f, state.tests[i].1)),
+                None => break,
+            }
+        }
+        match next {
+            Some((i, f, should_panic)) => {
+                drop(state);
+                let result = panic::catch_unwind(AssertUnwindSafe(f));
+                state = schedule.state.lock().unwrap();
+                if schedule.fail_fast && result.is_err() != should_panic {
+                    state.stopped = true;
+                }
+                state.results.insert(i, result);
+                schedule.finished.notify_all();
+            }
+            // The test of this slot is running in another slot
+            None => state = schedule.finished.wait(state).unwrap(),
+        }
+    }
+}
+
+/// Fisher-Yates shuffle driven by SplitMix64, so that a seed gives the same order on every
+/// platform and toolchain.
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    };
+    for i in (1..items.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
@@ -0,0 +1,207 @@
+//! Machine-readable reports of a test run: JUnit XML for CI dashboards and a JSON-lines event
+//! file with one object per test, as well as the list of failed tests for `Config::rerun_failed`.
+
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::common::Config;
+use crate::summary::{TestStatus, TestSummary};
+
+pub const JUNIT_REPORT: &str = "report.xml";
+pub const JSON_REPORT: &str = "report.jsonl";
+pub const FAILED_TESTS: &str = "failed-tests.txt";
+
+/// Where the JUnit report of a run with `config` is written.
+pub fn junit_report_path(config: &Config) -> PathBuf {
+    config.build_base.join(JUNIT_REPORT)
+}
+
+/// Where the JSON-lines report of a run with `config` is written.
+pub fn json_report_path(config: &Config) -> PathBuf {
+    config.build_base.join(JSON_REPORT)
+}
+
+/// Where the names of the tests that failed when they last ran are kept, one per line.
+pub fn failed_tests_path(config: &Config) -> PathBuf {
+    config.build_base.join(FAILED_TESTS)
+}
+
+/// The tests that failed when they last ran, or `None` if no run has recorded them yet.
+pub fn read_failed_tests(config: &Config) -> io::Result<Option<BTreeSet<String>>> {
+    match fs::read_to_string(failed_tests_path(config)) {
+        Ok(list) => Ok(Some(list.lines().map(str::to_owned).collect())),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Updates the list of failed tests with the outcomes in `summary`. Tests that didn't run, e.g.
+/// because of `filters`, stay in the list. The new list is written next to the old one and then
+/// renamed over it, so that a concurrent run never reads half a list.
+pub fn update_failed_tests(config: &Config, summary: &TestSummary) -> io::Result<()> {
+    static NEXT_TEMP: AtomicUsize = AtomicUsize::new(0);
+
+    let mut failed = read_failed_tests(config)?.unwrap_or_default();
+    for outcome in &summary.outcomes {
+        if outcome.status == TestStatus::Passed {
+            failed.remove(&outcome.name);
+        }
+    }
+    for outcome in summary.failures() {
+        failed.insert(outcome.name.clone());
+    }
+    let path = failed_tests_path(config);
+    let temp = path.with_extension(format!(
+        "{}.{}.tmp",
+        process::id(),
+        NEXT_TEMP.fetch_add(1, Ordering::Relaxed)
+    ));
+    let written = write_to(&temp, |w| {
+        failed.iter().try_for_each(|name| writeln!(w, "{}", name))
+    })
+    .and_then(|()| fs::rename(&temp, &path));
+    if written.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    written
+}
+
+/// Writes both reports for `summary` into `config.build_base`.
+pub fn write_reports(config: &Config, summary: &TestSummary) -> io::Result<()> {
+    write_to(&junit_report_path(config), |w| {
+        write_junit(config, summary, w)
+    })?;
+    write_to(&json_report_path(config), |w| write_json_lines(summary, w))
+}
+
+fn write_to(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    write(&mut w)?;
+    w.flush()
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Event<'a> {
+    Test {
+        name: &'a str,
+        mode: String,
+        revision: Option<&'a str>,
+        status: String,
+        exec_time: f64,
+        output: Option<&'a str>,
+    },
+    Suite {
+        passed: usize,
+        failed: usize,
+        ignored: usize,
+        exec_time: f64,
+    },
+}
+
+/// Writes one `{"type": "test", ...}` line per test, followed by a `{"type": "suite", ...}` line
+/// with the totals. `exec_time` is in seconds.
+pub fn write_json_lines(summary: &TestSummary, w: &mut dyn Write) -> io::Result<()> {
+    for outcome in &summary.outcomes {
+        let event = Event::Test {
+            name: &outcome.name,
+            mode: outcome.mode.to_string(),
+            revision: outcome.revision.as_deref(),
+            status: outcome.status.to_string(),
+            exec_time: outcome.duration.as_secs_f64(),
+            output: outcome.message.as_deref(),
+        };
+        serde_json::to_writer(&mut *w, &event)?;
+        writeln!(w)?;
+    }
+    let event = Event::Suite {
+        passed: summary.passed(),
+        failed: summary.failed(),
+        ignored: summary.ignored(),
+        exec_time: summary.duration.as_secs_f64(),
+    };
+    serde_json::to_writer(&mut *w, &event)?;
+    writeln!(w)
+}
+
+/// Writes `summary` as a single JUnit `<testsuite>` named after the mode.
+pub fn write_junit(config: &Config, summary: &TestSummary, w: &mut dyn Write) -> io::Result<()> {
+    writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(w, "<testsuites>")?;
+    writeln!(
+        w,
+        r#"  <testsuite name="{}" package="compiletest" id="0" errors="0" failures="{}" tests="{}" skipped="{}" time="{:.3}">"#,
+        config.mode,
+        summary.failed(),
+        summary.outcomes.len(),
+        summary.ignored(),
+        summary.duration.as_secs_f64(),
+    )?;
+    for outcome in &summary.outcomes {
+        write!(
+            w,
+            r#"    <testcase classname="{}" name="{}" time="{:.3}""#,
+            outcome.mode,
+            escape_xml(&outcome.name),
+            outcome.duration.as_secs_f64(),
+        )?;
+        if outcome.status == TestStatus::Passed && outcome.revision.is_none() {
+            writeln!(w, "/>")?;
+            continue;
+        }
+        writeln!(w, ">")?;
+        if let Some(ref revision) = outcome.revision {
+            writeln!(w, "      <properties>")?;
+            writeln!(
+                w,
+                r#"        <property name="revision" value="{}"/>"#,
+                escape_xml(revision)
+            )?;
+            writeln!(w, "      </properties>")?;
+        }
+        match outcome.status {
+            TestStatus::Passed => {}
+            TestStatus::Ignored => writeln!(w, "      <skipped/>")?,
+            TestStatus::Failed => {
+                let output = outcome.message.as_deref().unwrap_or("");
+                let message = output.lines().next().unwrap_or("test failed");
+                writeln!(
+                    w,
+                    r#"      <failure type="assert" message="{}"/>"#,
+                    escape_xml(message)
+                )?;
+                writeln!(
+                    w,
+                    "      <system-out><![CDATA[{}]]></system-out>",
+                    output.replace("]]>", "]]]]><![CDATA[>")
+                )?;
+            }
+        }
+        writeln!(w, "    </testcase>")?;
+    }
+    writeln!(w, "  </testsuite>")?;
+    writeln!(w, "</testsuites>")
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
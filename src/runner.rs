@@ -0,0 +1,114 @@
+//! User-defined test modes.
+//!
+//! A `TestRunner` registered with `register_mode` is run for every test of its mode, in place of
+//! one of the built-in runners:
+//!
+//! ```no_run
+//! use compiletest_rs::runner::{self, RunnerCx, TestRunner};
+//! use std::process::Command;
+//!
+//! struct Checked;
+//!
+//! impl TestRunner for Checked {
+//!     fn run(&self, cx: &RunnerCx) {
+//!         let proc_res = cx.compile();
+//!         if !proc_res.status().success() {
+//!             cx.fatal_proc_rec("compilation failed!", &proc_res);
+//!         }
+//!         let mut checker = Command::new("our-checker");
+//!         checker.arg(cx.exe_path());
+//!         let checked = cx.run(checker);
+//!         if cx.compare_output("checked", checked.stdout()) > 0 {
+//!             cx.fatal("checker output differs");
+//!         }
+//!     }
+//!
+//!     fn output_kinds(&self) -> &[&str] {
+//!         &["checked"]
+//!     }
+//! }
+//!
+//! let mut config = compiletest_rs::Config::default();
+//! config.mode = runner::register_mode("checked", Checked);
+//! ```
+//!
+//! Once registered, the name is also accepted by `Mode::from_str`, e.g. in a `compiletest.toml`.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::common::Mode;
+
+pub use crate::runtest::{ProcRes, RunnerCx};
+
+/// Runs the tests of a user-defined mode.
+pub trait TestRunner: Send + Sync {
+    /// Runs one revision of a test (or the whole test, if it has no revisions), panicking, e.g.
+    /// through `RunnerCx::fatal`, if it fails.
+    fn run(&self, cx: &RunnerCx);
+
+    /// Whether a file found in `src_base` is a test. Defaults to `.rs` files.
+    fn is_test(&self, file_name: &OsStr) -> bool {
+        crate::is_test(&file_name.to_os_string())
+    }
+
+    /// The kinds of expected output that `run` passes to `RunnerCx::compare_output`. Changes to
+    /// those files make a test out of date, like changes to `.stderr` files do for ui tests.
+    fn output_kinds(&self) -> &[&str] {
+        &[]
+    }
+}
+
+/// A mode registered with `register_mode`, which is the only way to create one, so that every
+/// `CustomMode` has a runner.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct CustomMode(&'static str);
+
+impl CustomMode {
+    /// The name the mode was registered under
+    pub fn name(self) -> &'static str {
+        self.0
+    }
+}
+
+fn runners() -> &'static RwLock<HashMap<&'static str, Arc<dyn TestRunner>>> {
+    static RUNNERS: OnceLock<RwLock<HashMap<&'static str, Arc<dyn TestRunner>>>> = OnceLock::new();
+    RUNNERS.get_or_init(Default::default)
+}
+
+/// Registers `runner` for the tests of the mode `name`, replacing any runner previously
+/// registered under that name, and returns the mode.
+///
+/// # Panics
+///
+/// If `name` is the name of a built-in mode.
+pub fn register_mode<R: TestRunner + 'static>(name: &str, runner: R) -> Mode {
+    if let Ok(mode) = name.parse::<Mode>() {
+        if !matches!(mode, Mode::Custom(_)) {
+            panic!("`{}` is a built-in mode", name);
+        }
+    }
+
+    let mut runners = runners().write().unwrap();
+    let name = match runners.get_key_value(name) {
+        Some((&name, _)) => name,
+        None => Box::leak(name.to_owned().into_boxed_str()),
+    };
+    runners.insert(name, Arc::new(runner));
+    Mode::Custom(CustomMode(name))
+}
+
+/// The mode registered under `name`, if any.
+pub(crate) fn custom_mode(name: &str) -> Option<Mode> {
+    let runners = runners().read().unwrap();
+    runners
+        .get_key_value(name)
+        .map(|(&name, _)| Mode::Custom(CustomMode(name)))
+}
+
+/// The runner of `mode`. Runners are never unregistered, so there always is one.
+pub(crate) fn runner(mode: CustomMode) -> Arc<dyn TestRunner> {
+    let runners = runners().read().unwrap();
+    Arc::clone(&runners[mode.0])
+}
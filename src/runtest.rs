@@ -8,15 +8,18 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use crate::common::{expected_output_path, UI_FIXED, UI_STDERR, UI_STDOUT};
-use crate::common::{Assembly, Incremental, MirOpt, RunMake, Ui};
+use crate::common::{aux_file_path, expected_output_path_with_features};
+use crate::common::{Assembly, Custom, Incremental, MirOpt, RunMake, Ui};
 use crate::common::{Codegen, CodegenUnits, DebugInfoGdb, DebugInfoLldb, Rustdoc};
 use crate::common::{CompileFail, ParseFail, Pretty, RunFail, RunPass, RunPassValgrind};
 use crate::common::{Config, TestPaths};
+use crate::common::{UI_FIXED, UI_STDERR, UI_STDOUT};
 use crate::errors::{self, Error, ErrorKind};
 use crate::header::TestProps;
+use crate::hooks::{HookCx, ProcessKind};
 use crate::json;
-use crate::util::{logv, PathBufExt};
+use crate::suites::DirDefaults;
+use crate::util::{self, logv, PathBufExt};
 use filetime::FileTime;
 use regex::Regex;
 use rustfix::{apply_suggestions, get_suggestions_from_json, Filter};
@@ -32,7 +35,9 @@ use std::io::{self, BufReader};
 use std::path::{Path, PathBuf};
 use std::process::{Child, Command, ExitStatus, Output, Stdio};
 use std::str;
-use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::extract_gdb_version;
 
@@ -115,7 +120,11 @@ pub fn run(config: Config, testpaths: &TestPaths) {
 
     base_cx.complete_all();
 
-    File::create(crate::stamp(&config, testpaths)).unwrap();
+    fs::write(
+        crate::stamp(&config, testpaths),
+        crate::compute_stamp_hash(&config),
+    )
+    .unwrap();
 }
 
 struct TestCx<'test> {
@@ -132,6 +141,17 @@ struct DebuggerCommands {
 }
 
 impl<'test> TestCx<'test> {
+    /// Fails the test if the headers of its file (or of this aux file) are malformed.
+    fn check_props(&self) {
+        if !self.props.errors.is_empty() {
+            self.fatal(&format!(
+                "{}: {}",
+                self.testpaths.file.display(),
+                self.props.errors.join("\n")
+            ));
+        }
+    }
+
     /// invoked once before any revisions have been processed
     fn init_all(&self) {
         assert!(self.revision.is_none(), "init_all invoked for a revision");
@@ -143,6 +163,7 @@ impl<'test> TestCx<'test> {
     /// Code executed for each revision in turn (or, if there are no
     /// revisions, exactly once, with revision == None).
     fn run_revision(&self) {
+        self.check_props();
         match self.config.mode {
             CompileFail | ParseFail => self.run_cfail_test(),
             RunFail => self.run_rfail_test(),
@@ -159,6 +180,7 @@ impl<'test> TestCx<'test> {
             Ui => self.run_ui_test(),
             MirOpt => self.run_mir_opt_test(),
             Assembly => self.run_assembly_test(),
+            Custom(mode) => crate::runner::runner(mode).run(&RunnerCx { cx: self }),
         }
     }
 
@@ -186,7 +208,7 @@ impl<'test> TestCx<'test> {
         }
 
         let output_to_check = self.get_output(&proc_res);
-        let expected_errors = errors::load_errors(&self.testpaths.file, self.revision);
+        let expected_errors = errors::load_errors(&self.testpaths.root_file(), self.revision);
         if !expected_errors.is_empty() {
             if !self.props.error_patterns.is_empty() {
                 self.fatal("both error pattern and expected errors specified");
@@ -250,7 +272,7 @@ impl<'test> TestCx<'test> {
         }
 
         // FIXME(#41968): Move this check to tidy?
-        let expected_errors = errors::load_errors(&self.testpaths.file, self.revision);
+        let expected_errors = errors::load_errors(&self.testpaths.root_file(), self.revision);
         assert!(
             expected_errors.is_empty(),
             "run-pass tests with expected warnings should be moved to ui/"
@@ -278,7 +300,7 @@ impl<'test> TestCx<'test> {
         }
 
         let mut new_config = self.config.clone();
-        new_config.runtool = new_config.valgrind_path.clone();
+        new_config.runtool = new_config.valgrind_path.as_deref().map(util::quote_arg);
         let new_cx = TestCx {
             config: &new_config,
             ..*self
@@ -406,11 +428,14 @@ impl<'test> TestCx<'test> {
             .arg("-L")
             .arg(&aux_dir)
             .args(self.split_maybe_args(&self.config.target_rustcflags))
+            .args(&self.config.target_rustc_args)
+            .args(self.config.dependencies.rustc_args())
             .args(&self.props.compile_flags)
             .envs(self.props.exec_env.clone());
 
         self.compose_and_run(
             rustc,
+            ProcessKind::Compile,
             self.config.compile_lib_path.to_str().unwrap(),
             Some(aux_dir.to_str().unwrap()),
             Some(src),
@@ -468,6 +493,8 @@ actual:\n\
         }
 
         rustc.args(self.split_maybe_args(&self.config.target_rustcflags));
+        rustc.args(&self.config.target_rustc_args);
+        rustc.args(self.config.dependencies.rustc_args());
         rustc.args(&self.props.compile_flags);
 
         self.compose_and_run_compiler(rustc, Some(src))
@@ -730,6 +757,7 @@ actual:\n\
 
                 debugger_run_result = self.compose_and_run(
                     gdb,
+                    ProcessKind::Run,
                     self.config.run_lib_path.to_str().unwrap(),
                     None,
                     None,
@@ -953,7 +981,13 @@ actual:\n\
             .filter(|x| !options_to_remove.contains(x))
             .collect::<Vec<String>>();
 
-        Some(new_options.join(" "))
+        Some(
+            new_options
+                .iter()
+                .map(|option| util::quote_arg(option))
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
     }
 
     fn check_debugger_output(&self, debugger_run_result: &ProcRes, check_lines: &[String]) {
@@ -1092,9 +1126,10 @@ actual:\n\
 
         // On Windows, keep all '\' path separators to match the paths reported in the JSON output
         // from the compiler
-        let os_file_name = self.testpaths.file.display().to_string();
+        let root_file = self.testpaths.root_file();
+        let os_file_name = root_file.display().to_string();
 
-        let file_name = format!("{}", self.testpaths.file.display()).replace(r"\", "/"); // on windows, translate all '\' path separators to '/'
+        let file_name = format!("{}", root_file.display()).replace(r"\", "/"); // on windows, translate all '\' path separators to '/'
 
         // If the testcase being checked contains at least one expected "help"
         // message, then we'll ensure that all "help" messages are expected.
@@ -1215,17 +1250,33 @@ actual:\n\
             _ => AllowUnused::No,
         };
 
+        let root_file = self.testpaths.root_file();
         let mut rustc = self.make_compile_args(
-            &self.testpaths.file,
+            &root_file,
             TargetLocation::ThisFile(self.make_exe_name()),
             allow_unused,
         );
 
         rustc.arg("-L").arg(&self.aux_output_dir_name());
+        if self.is_library() {
+            rustc.args(["--crate-type", "lib"]);
+        }
 
         self.compose_and_run_compiler(rustc, None)
     }
 
+    /// Whether this is a multi-file test with a `lib.rs`, which is built as a library unless its
+    /// `compile-flags` set another crate type.
+    fn is_library(&self) -> bool {
+        self.testpaths.file.is_dir()
+            && self.testpaths.root_file().ends_with("lib.rs")
+            && !self
+                .props
+                .compile_flags
+                .iter()
+                .any(|s| s.starts_with("--crate-type"))
+    }
+
     fn document(&self, out_dir: &Path) -> ProcRes {
         if self.props.build_aux_docs {
             for rel_ab in &self.props.aux_builds {
@@ -1260,7 +1311,8 @@ actual:\n\
             .arg(aux_dir)
             .arg("-o")
             .arg(out_dir)
-            .arg(&self.testpaths.file)
+            .arg(self.testpaths.root_file())
+            .args(self.config.dependencies.rustc_args())
             .args(&self.props.compile_flags);
         if let Some(ref linker) = self.config.linker {
             rustdoc
@@ -1274,6 +1326,12 @@ actual:\n\
     }
 
     fn exec_compiled_test(&self) -> ProcRes {
+        if self.is_library() {
+            self.fatal(
+                "multi-file tests with a `lib.rs` are built as libraries and can't be run; \
+                 add a `main.rs` or `--crate-type bin` to the `compile-flags`",
+            );
+        }
         let env = &self.props.exec_env;
 
         match &*self.config.target {
@@ -1311,6 +1369,7 @@ actual:\n\
                     .envs(env.clone());
                 self.compose_and_run(
                     test_client,
+                    ProcessKind::Run,
                     self.config.run_lib_path.to_str().unwrap(),
                     Some(aux_dir.to_str().unwrap()),
                     None,
@@ -1326,6 +1385,7 @@ actual:\n\
                     .envs(env.clone());
                 self.compose_and_run(
                     program,
+                    ProcessKind::Run,
                     self.config.run_lib_path.to_str().unwrap(),
                     Some(aux_dir.to_str().unwrap()),
                     None,
@@ -1335,15 +1395,10 @@ actual:\n\
     }
 
     /// For each `aux-build: foo/bar` annotation, we check to find the
-    /// file in a `aux` directory relative to the test itself.
+    /// file in a `aux` directory relative to the test itself, or in one
+    /// of the `aux_dirs`.
     fn compute_aux_test_paths(&self, rel_ab: &str) -> TestPaths {
-        let test_ab = self
-            .testpaths
-            .file
-            .parent()
-            .expect("test file path has no parent")
-            .join("auxiliary")
-            .join(rel_ab);
+        let test_ab = aux_file_path(self.config, self.testpaths, rel_ab);
         if !test_ab.exists() {
             self.fatal(&format!(
                 "aux-build `{}` source not found",
@@ -1351,43 +1406,59 @@ actual:\n\
             ))
         }
 
+        let relative_dir = self
+            .testpaths
+            .relative_dir
+            .join("auxiliary")
+            .join(rel_ab)
+            .parent()
+            .expect("aux-build path has no parent")
+            .to_path_buf();
+        // Only created by `collect_tests_from_dir` if the test has an `auxiliary` directory
+        create_dir_all(self.config.build_base.join(&relative_dir)).unwrap();
+
         TestPaths {
             file: test_ab,
             base: self.testpaths.base.clone(),
-            relative_dir: self
-                .testpaths
-                .relative_dir
-                .join("auxiliary")
-                .join(rel_ab)
-                .parent()
-                .expect("aux-build path has no parent")
-                .to_path_buf(),
+            relative_dir,
         }
     }
 
     fn compose_and_run_compiler(&self, mut rustc: Command, input: Option<String>) -> ProcRes {
+        let aux_dir = self.aux_output_dir_name();
+        self.build_all_auxiliary(&aux_dir);
+
+        rustc.envs(self.props.rustc_env.clone());
+        self.compose_and_run(
+            rustc,
+            ProcessKind::Compile,
+            self.config.compile_lib_path.to_str().unwrap(),
+            Some(aux_dir.to_str().unwrap()),
+            input,
+        )
+    }
+
+    /// Builds the `aux-build`s of this test, or of this auxiliary crate, into `aux_dir`, each
+    /// after its own `aux-build`s.
+    fn build_all_auxiliary(&self, aux_dir: &Path) {
         if !self.props.aux_builds.is_empty() {
-            create_dir_all(&self.aux_output_dir_name()).unwrap();
+            create_dir_all(aux_dir).unwrap();
         }
 
-        let aux_dir = self.aux_output_dir_name();
-
         for rel_ab in &self.props.aux_builds {
             let aux_testpaths = self.compute_aux_test_paths(rel_ab);
             let aux_props =
                 self.props
                     .from_aux_file(&aux_testpaths.file, self.revision, self.config);
-            let aux_output = {
-                let f = self.make_lib_name(&self.testpaths.file);
-                let parent = f.parent().unwrap();
-                TargetLocation::ThisDirectory(parent.to_path_buf())
-            };
+            let aux_output = TargetLocation::ThisDirectory(aux_dir.to_path_buf());
             let aux_cx = TestCx {
                 config: self.config,
                 props: &aux_props,
                 testpaths: &aux_testpaths,
                 revision: self.revision,
             };
+            aux_cx.check_props();
+            aux_cx.build_all_auxiliary(aux_dir);
             let mut aux_rustc =
                 aux_cx.make_compile_args(&aux_testpaths.file, aux_output, AllowUnused::No);
 
@@ -1415,10 +1486,11 @@ actual:\n\
                 aux_rustc.args(&["--crate-type", crate_type]);
             }
 
-            aux_rustc.arg("-L").arg(&aux_dir);
+            aux_rustc.arg("-L").arg(aux_dir);
 
             let auxres = aux_cx.compose_and_run(
                 aux_rustc,
+                ProcessKind::Compile,
                 aux_cx.config.compile_lib_path.to_str().unwrap(),
                 Some(aux_dir.to_str().unwrap()),
                 None,
@@ -1433,23 +1505,26 @@ actual:\n\
                 );
             }
         }
-
-        rustc.envs(self.props.rustc_env.clone());
-        self.compose_and_run(
-            rustc,
-            self.config.compile_lib_path.to_str().unwrap(),
-            Some(aux_dir.to_str().unwrap()),
-            input,
-        )
     }
 
     fn compose_and_run(
         &self,
         mut command: Command,
+        kind: ProcessKind,
         lib_path: &str,
         aux_path: Option<&str>,
         input: Option<String>,
     ) -> ProcRes {
+        let hook_cx = HookCx {
+            kind,
+            config: self.config,
+            paths: self.testpaths,
+            revision: self.revision,
+        };
+        for hook in &self.config.hooks {
+            hook.before(&hook_cx, &mut command);
+        }
+
         let cmdline = {
             let cmdline = self.make_cmdline(&command, lib_path);
             logv(self.config, format!("executing {}", cmdline));
@@ -1474,6 +1549,12 @@ actual:\n\
         let newpath = env::join_paths(&path).unwrap();
         command.env(dylib_env_var(), newpath);
 
+        let timeout = self.timeout();
+        if timeout.is_some() {
+            set_process_group(&mut command);
+        }
+
+        let start = Instant::now();
         let mut child = command
             .spawn()
             .expect(&format!("failed to exec `{:?}`", &command));
@@ -1486,24 +1567,49 @@ actual:\n\
                 .unwrap();
         }
 
-        let Output {
-            status,
-            stdout,
-            stderr,
-        } = read2_abbreviated(child).expect("failed to read output");
+        let (
+            Output {
+                status,
+                stdout,
+                stderr,
+            },
+            timed_out,
+        ) = read2_abbreviated_with_timeout(child, timeout).expect("failed to read output");
 
-        let result = ProcRes {
+        let mut result = ProcRes {
             status,
             stdout: String::from_utf8_lossy(&stdout).into_owned(),
             stderr: String::from_utf8_lossy(&stderr).into_owned(),
             cmdline,
         };
+        let duration = start.elapsed();
+        for hook in &self.config.hooks {
+            hook.after(&hook_cx, &mut result, duration);
+        }
 
         self.dump_output(&result.stdout, &result.stderr);
 
+        if timed_out {
+            self.fatal_timed_out(&result);
+        }
+
         result
     }
 
+    /// The time after which processes spawned for this test are killed: the `timeout`
+    /// header if present, otherwise `Config::timeout`.
+    fn timeout(&self) -> Option<Duration> {
+        self.props.timeout.or(self.config.timeout)
+    }
+
+    fn fatal_timed_out(&self, proc_res: &ProcRes) -> ! {
+        let timeout = self.timeout().unwrap_or_default();
+        self.fatal_proc_rec(
+            &format!("timed out after {} s", timeout.as_secs_f64()),
+            proc_res,
+        )
+    }
+
     fn make_compile_args(
         &self,
         input_file: &Path,
@@ -1576,7 +1682,7 @@ actual:\n\
                 }
             }
             RunFail | RunPassValgrind | Pretty | DebugInfoGdb | DebugInfoLldb | Codegen
-            | Rustdoc | RunMake | CodegenUnits | Assembly => {
+            | Rustdoc | RunMake | CodegenUnits | Assembly | Custom(_) => {
                 // do not use JSON output
             }
         }
@@ -1606,7 +1712,9 @@ actual:\n\
             rustc.args(self.split_maybe_args(&self.config.host_rustcflags));
         } else {
             rustc.args(self.split_maybe_args(&self.config.target_rustcflags));
+            rustc.args(&self.config.target_rustc_args);
         }
+        rustc.args(self.config.dependencies.rustc_args());
         if let Some(ref linker) = self.config.linker {
             rustc.arg(format!("-Clinker={}", linker));
         }
@@ -1616,13 +1724,6 @@ actual:\n\
         rustc
     }
 
-    fn make_lib_name(&self, auxfile: &Path) -> PathBuf {
-        // what we return here is not particularly important, as it
-        // happens; rustc ignores everything except for the directory.
-        let auxname = self.output_testname(auxfile);
-        self.aux_output_dir_name().join(&auxname)
-    }
-
     fn make_exe_name(&self) -> PathBuf {
         let mut f = self.output_base_name();
         // FIXME: This is using the host architecture exe suffix, not target!
@@ -1695,16 +1796,7 @@ actual:\n\
 
     fn split_maybe_args(&self, argstr: &Option<String>) -> Vec<String> {
         match *argstr {
-            Some(ref s) => s
-                .split(' ')
-                .filter_map(|s| {
-                    if s.chars().all(|c| c.is_whitespace()) {
-                        None
-                    } else {
-                        Some(s.to_owned())
-                    }
-                })
-                .collect(),
+            Some(ref s) => util::split_args(s).unwrap_or_else(|e| self.fatal(&e)),
             None => Vec::new(),
         }
     }
@@ -1794,13 +1886,17 @@ actual:\n\
 
     fn fatal(&self, err: &str) -> ! {
         self.error(err);
-        panic!();
+        // Like `ProcRes::fail`, so that the failure is reported with its message and revision.
+        std::panic::resume_unwind(Box::new(TestFailure {
+            revision: self.revision.map(str::to_owned),
+            message: err.to_owned(),
+        }));
     }
 
     fn fatal_proc_rec(&self, err: &str, proc_res: &ProcRes) -> ! {
         self.try_print_open_handles();
         self.error(err);
-        proc_res.fatal(None);
+        proc_res.fail(self.revision, Some(err));
     }
 
     // This function is a poor man's attempt to debug rust-lang/rust#38620, if
@@ -1849,7 +1945,8 @@ actual:\n\
 
         let output_file =
             TargetLocation::ThisDirectory(self.output_base_name().parent().unwrap().to_path_buf());
-        let mut rustc = self.make_compile_args(&self.testpaths.file, output_file, AllowUnused::No);
+        let mut rustc =
+            self.make_compile_args(&self.testpaths.root_file(), output_file, AllowUnused::No);
         rustc.arg("-L").arg(aux_dir).arg("--emit=llvm-ir");
 
         self.compose_and_run_compiler(rustc, None)
@@ -1861,7 +1958,8 @@ actual:\n\
         let output_path = self.output_base_name().with_extension("s");
 
         let output_file = TargetLocation::ThisFile(output_path.clone());
-        let mut rustc = self.make_compile_args(&self.testpaths.file, output_file, AllowUnused::No);
+        let mut rustc =
+            self.make_compile_args(&self.testpaths.root_file(), output_file, AllowUnused::No);
 
         rustc.arg("-L").arg(self.aux_output_dir_name());
 
@@ -1906,7 +2004,7 @@ actual:\n\
             .arg("--check-prefixes")
             .arg(&prefixes)
             .arg(&self.testpaths.file);
-        let proc_res = self.compose_and_run(filecheck, "", None, None);
+        let proc_res = self.compose_and_run(filecheck, ProcessKind::Run, "", None, None);
         if !proc_res.status.success() {
             self.fatal_proc_rec("verification with 'FileCheck' failed", &proc_res);
         }
@@ -2460,17 +2558,25 @@ actual:\n\
             }
         }
 
-        let output = cmd
+        let timeout = self.timeout();
+        if timeout.is_some() {
+            set_process_group(&mut cmd);
+        }
+
+        let (output, timed_out) = cmd
             .spawn()
-            .and_then(read2_abbreviated)
+            .and_then(|child| read2_abbreviated_with_timeout(child, timeout))
             .expect("failed to spawn `make`");
-        if !output.status.success() {
+        if timed_out || !output.status.success() {
             let res = ProcRes {
                 status: output.status,
                 stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
                 stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
                 cmdline: format!("{:?}", cmd),
             };
+            if timed_out {
+                self.fatal_timed_out(&res);
+            }
             self.fatal_proc_rec("make failed", &res);
         }
     }
@@ -2519,7 +2625,7 @@ actual:\n\
         let expected_fixed = self.load_expected_output(&expected_fixed_path);
 
         let normalized_stdout =
-            self.normalize_output(&proc_res.stdout, &self.props.normalize_stdout);
+            self.normalize_output(&proc_res.stdout, &self.normalization_rules(UI_STDOUT));
 
         let stderr = if explicit {
             proc_res.stderr.clone()
@@ -2527,7 +2633,8 @@ actual:\n\
             json::extract_rendered(&proc_res.stderr, &proc_res)
         };
 
-        let normalized_stderr = self.normalize_output(&stderr, &self.props.normalize_stderr);
+        let normalized_stderr =
+            self.normalize_output(&stderr, &self.normalization_rules(UI_STDERR));
 
         let mut errors = 0;
         errors += self.compare_output(UI_STDOUT, &normalized_stdout, &expected_stdout);
@@ -2573,6 +2680,9 @@ actual:\n\
         }
 
         if self.props.run_rustfix {
+            if self.testpaths.file.is_dir() {
+                self.fatal("`run-rustfix` is not supported in multi-file tests");
+            }
             // Apply suggestions from lints to the code itself
             let unfixed_code = self
                 .load_expected_output_from_path(&self.testpaths.file)
@@ -2835,6 +2945,41 @@ actual:\n\
         mir_dump_dir
     }
 
+    /// The rules `normalize_output` applies to the `UI_STDOUT` or `UI_STDERR` of the test, in
+    /// order: those of `config.normalize_presets`, of `config`, of the `compiletest-defaults.toml`
+    /// files from the test's base directory down to its own directory, and of its headers.
+    fn normalization_rules(&self, kind: &str) -> Vec<(String, String)> {
+        let defaults =
+            DirDefaults::for_test(self.testpaths).unwrap_or_else(|e| self.fatal(&e.to_string()));
+        let (config_rules, defaults_rules, props_rules) = if kind == UI_STDOUT {
+            (
+                &self.config.normalize_stdout,
+                defaults.normalize_stdout,
+                &self.props.normalize_stdout,
+            )
+        } else {
+            (
+                &self.config.normalize_stderr,
+                defaults.normalize_stderr,
+                &self.props.normalize_stderr,
+            )
+        };
+        let presets = self
+            .config
+            .normalize_presets
+            .iter()
+            .filter_map(|preset| preset.rule(self.config));
+        presets
+            .chain(config_rules.iter().cloned())
+            .chain(defaults_rules)
+            .chain(props_rules.iter().cloned())
+            .collect()
+    }
+
+    /// Replaces the paths of the test directory, `CARGO_MANIFEST_DIR` and `build_base` with
+    /// `$DIR`, `$SRC_DIR` and `$TEST_BUILD_DIR`, turns backslashes into slashes, CRLF into LF
+    /// and tabs into `\t`, and only then applies `custom_rules` in order, so that the rules see
+    /// the output as it is written to the expected output file.
     fn normalize_output(&self, output: &str, custom_rules: &[(String, String)]) -> String {
         let cflags = self.props.compile_flags.join(" ");
         let json = cflags.contains("--error-format json")
@@ -2887,13 +3032,7 @@ actual:\n\
     }
 
     fn expected_output_path(&self, kind: &str) -> PathBuf {
-        let mut path = expected_output_path(&self.testpaths, self.revision, kind);
-
-        if !path.exists() {
-            path = expected_output_path(&self.testpaths, self.revision, kind);
-        }
-
-        path
+        expected_output_path_with_features(self.config, self.testpaths, self.revision, kind)
     }
 
     fn load_expected_output(&self, path: &Path) -> String {
@@ -2959,7 +3098,7 @@ actual:\n\
 
         let mut files = vec![output_file];
         if self.config.bless {
-            files.push(expected_output_path(self.testpaths, self.revision, kind));
+            files.push(self.expected_output_path(kind));
         }
 
         for output_file in &files {
@@ -2987,6 +3126,83 @@ actual:\n\
     }
 }
 
+/// What a `runner::TestRunner` gets to run a test with.
+pub struct RunnerCx<'test> {
+    cx: &'test TestCx<'test>,
+}
+
+impl<'test> RunnerCx<'test> {
+    pub fn config(&self) -> &'test Config {
+        self.cx.config
+    }
+
+    /// The headers of the test, for the revision being run
+    pub fn props(&self) -> &'test TestProps {
+        self.cx.props
+    }
+
+    pub fn paths(&self) -> &'test TestPaths {
+        self.cx.testpaths
+    }
+
+    pub fn revision(&self) -> Option<&'test str> {
+        self.cx.revision
+    }
+
+    /// Compiles the test and its `aux-build`s into `exe_path`, with the flags from the `Config`
+    /// and the headers, like the built-in modes do.
+    pub fn compile(&self) -> ProcRes {
+        self.cx.compile_test()
+    }
+
+    /// Where `compile` puts the compiled test.
+    pub fn exe_path(&self) -> PathBuf {
+        self.cx.make_exe_name()
+    }
+
+    /// The path in `build_base` that outputs of the test are named after, e.g.
+    /// `<build_base>/foo/bar.stage-id` for `foo/bar.rs`.
+    pub fn output_base_name(&self) -> PathBuf {
+        self.cx.output_base_name()
+    }
+
+    /// Runs `command` with the test's `timeout`, failing the test if it times out.
+    pub fn run(&self, command: Command) -> ProcRes {
+        let aux_dir = self.cx.aux_output_dir_name();
+        self.cx.compose_and_run(
+            command,
+            ProcessKind::Run,
+            self.cx.config.run_lib_path.to_str().unwrap(),
+            Some(aux_dir.to_str().unwrap()),
+            None,
+        )
+    }
+
+    /// Normalizes paths and line endings in `output` like ui tests do, then applies the `rules`
+    /// (e.g. `props().normalize_stderr`).
+    pub fn normalize_output(&self, output: &str, rules: &[(String, String)]) -> String {
+        self.cx.normalize_output(output, rules)
+    }
+
+    /// Compares `actual` to the expected output of kind `kind`, in `<test>.<kind>` next to the
+    /// test, printing a diff or, with `Config::bless`, updating the expected file. Returns the
+    /// number of mismatches that weren't blessed.
+    pub fn compare_output(&self, kind: &str, actual: &str) -> usize {
+        let expected = self
+            .cx
+            .load_expected_output(&self.cx.expected_output_path(kind));
+        self.cx.compare_output(kind, actual, &expected)
+    }
+
+    pub fn fatal(&self, err: &str) -> ! {
+        self.cx.fatal(err)
+    }
+
+    pub fn fatal_proc_rec(&self, err: &str, proc_res: &ProcRes) -> ! {
+        self.cx.fatal_proc_rec(err, proc_res)
+    }
+}
+
 struct ProcArgs {
     prog: String,
     args: Vec<String>,
@@ -3000,11 +3216,42 @@ pub struct ProcRes {
 }
 
 impl ProcRes {
+    pub fn status(&self) -> ExitStatus {
+        self.status
+    }
+
+    pub fn stdout(&self) -> &str {
+        &self.stdout
+    }
+
+    pub fn stderr(&self) -> &str {
+        &self.stderr
+    }
+
+    /// The command line of the process, for error messages
+    pub fn cmdline(&self) -> &str {
+        &self.cmdline
+    }
+
+    /// For `hooks::Hook::after` to rewrite the output before it is checked
+    pub fn stdout_mut(&mut self) -> &mut String {
+        &mut self.stdout
+    }
+
+    /// For `hooks::Hook::after` to rewrite the output before it is checked
+    pub fn stderr_mut(&mut self) -> &mut String {
+        &mut self.stderr
+    }
+
     pub fn fatal(&self, err: Option<&str>) -> ! {
         if let Some(e) = err {
             println!("\nerror: {}", e);
         }
-        print!(
+        self.fail(None, err);
+    }
+
+    fn fail(&self, revision: Option<&str>, err: Option<&str>) -> ! {
+        let output = format!(
             "\
             status: {}\n\
             command: {}\n\
@@ -3019,12 +3266,27 @@ impl ProcRes {
             \n",
             self.status, self.cmdline, self.stdout, self.stderr
         );
+        print!("{}", output);
+        let message = match err {
+            Some(e) => format!("error: {}\n{}", e, output),
+            None => output,
+        };
         // Use resume_unwind instead of panic!() to prevent a panic message + backtrace from
         // compiletest, which is unnecessary noise.
-        std::panic::resume_unwind(Box::new(()));
+        std::panic::resume_unwind(Box::new(TestFailure {
+            revision: revision.map(str::to_owned),
+            message,
+        }));
     }
 }
 
+/// Panic payload of a test that failed through `TestCx::fatal` or `ProcRes::fatal`, carrying the
+/// text that was printed so `run_tests_with_summary` can report it.
+pub(crate) struct TestFailure {
+    pub revision: Option<String>,
+    pub message: String,
+}
+
 enum TargetLocation {
     ThisFile(PathBuf),
     ThisDirectory(PathBuf),
@@ -3067,7 +3329,17 @@ fn nocomment_mir_line(line: &str) -> &str {
     }
 }
 
-fn read2_abbreviated(mut child: Child) -> io::Result<Output> {
+fn read2_abbreviated(child: Child) -> io::Result<Output> {
+    read2_abbreviated_with_timeout(child, None).map(|(output, _)| output)
+}
+
+/// Reads the (possibly abbreviated) output of `child` and waits for it to exit. If `timeout`
+/// passes first, the child is killed, along with its process group on Unix and its process
+/// tree on Windows. Returns the output captured until then and whether the child was killed.
+fn read2_abbreviated_with_timeout(
+    mut child: Child,
+    timeout: Option<Duration>,
+) -> io::Result<(Output, bool)> {
     use crate::read2::read2;
     use std::mem::replace;
 
@@ -3139,6 +3411,8 @@ fn read2_abbreviated(mut child: Child) -> io::Result<Output> {
     let mut stdout = ProcOutput::Full(Vec::new());
     let mut stderr = ProcOutput::Full(Vec::new());
 
+    let watchdog = timeout.map(|timeout| Watchdog::start(child.id(), timeout));
+
     drop(child.stdin.take());
     read2(
         child.stdout.take().unwrap(),
@@ -3148,11 +3422,98 @@ fn read2_abbreviated(mut child: Child) -> io::Result<Output> {
             data.clear();
         },
     )?;
-    let status = child.wait()?;
+    let (status, timed_out) = match watchdog {
+        Some(watchdog) => watchdog.wait(&mut child)?,
+        None => (child.wait()?, false),
+    };
 
-    Ok(Output {
-        status,
-        stdout: stdout.into_bytes(),
-        stderr: stderr.into_bytes(),
-    })
+    Ok((
+        Output {
+            status,
+            stdout: stdout.into_bytes(),
+            stderr: stderr.into_bytes(),
+        },
+        timed_out,
+    ))
 }
+
+/// A thread that kills a child process once its timeout has passed, unless the process has
+/// been reaped by `Watchdog::wait` before.
+struct Watchdog {
+    // `true` once the child has been reaped, so that its pid may have been reused.
+    reaped: Arc<(Mutex<bool>, Condvar)>,
+    thread: thread::JoinHandle<bool>,
+}
+
+impl Watchdog {
+    fn start(pid: u32, timeout: Duration) -> Self {
+        let reaped = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread = {
+            let reaped = Arc::clone(&reaped);
+            thread::spawn(move || {
+                let (lock, cvar) = &*reaped;
+                let (reaped, _) = cvar
+                    .wait_timeout_while(lock.lock().unwrap(), timeout, |reaped| !*reaped)
+                    .unwrap();
+                if *reaped {
+                    return false;
+                }
+                kill_process_tree(pid);
+                true
+            })
+        };
+        Watchdog { reaped, thread }
+    }
+
+    /// Waits for `child` to exit. Returns its exit status and whether it was killed.
+    fn wait(self, child: &mut Child) -> io::Result<(ExitStatus, bool)> {
+        let (lock, cvar) = &*self.reaped;
+        let status = loop {
+            // Reap the child while holding the lock so the watchdog can't kill an unrelated
+            // process that was given the same pid.
+            let mut reaped = lock.lock().unwrap();
+            if let Some(status) = child.try_wait()? {
+                *reaped = true;
+                cvar.notify_one();
+                break status;
+            }
+            drop(reaped);
+            thread::sleep(Duration::from_millis(10));
+        };
+        let timed_out = self.thread.join().unwrap_or(false);
+        Ok((status, timed_out))
+    }
+}
+
+/// Puts the process spawned by `command` in a new process group, so that it can be killed
+/// along with any children it spawns.
+#[cfg(unix)]
+fn set_process_group(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+
+    command.process_group(0);
+}
+
+#[cfg(not(unix))]
+fn set_process_group(_command: &mut Command) {}
+
+#[cfg(unix)]
+fn kill_process_tree(pid: u32) {
+    let pid = pid as libc::pid_t;
+    unsafe {
+        libc::kill(-pid, libc::SIGKILL);
+        libc::kill(pid, libc::SIGKILL);
+    }
+}
+
+#[cfg(windows)]
+fn kill_process_tree(pid: u32) {
+    let _ = Command::new("taskkill")
+        .args(&["/F", "/T", "/PID", &pid.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
+
+#[cfg(not(any(unix, windows)))]
+fn kill_process_tree(_pid: u32) {}
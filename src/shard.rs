@@ -0,0 +1,101 @@
+//! Splitting a suite across machines with `Config::shard_index` and `Config::shard_count`.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::common::Config;
+
+/// A hash of `name` that is the same on every machine and with every compiler version, unlike
+/// `DefaultHasher`: 64-bit FNV-1a.
+pub fn stable_hash(name: &str) -> u64 {
+    name.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Whether each of the tests `names` belongs to the shard `config.shard_index`.
+pub(crate) fn select(config: &Config, names: &[String]) -> io::Result<Vec<bool>> {
+    if config.shard_count == 0 || config.shard_index >= config.shard_count {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "invalid shard {} of {}",
+                config.shard_index, config.shard_count
+            ),
+        ));
+    }
+    let shards = if config.shard_weights.is_empty() {
+        names
+            .iter()
+            .map(|name| (stable_hash(name) % config.shard_count as u64) as usize)
+            .collect()
+    } else {
+        let durations = merge_durations(&config.shard_weights)?;
+        weighted_shards(names, &durations, config.shard_count)
+    };
+    Ok(shards
+        .into_iter()
+        .map(|shard| shard == config.shard_index)
+        .collect())
+}
+
+/// Assigns each test to a shard so that the durations add up to about the same time in every
+/// shard: the longest test goes first into the shard with the least time so far. Tests that
+/// aren't in `durations` are assumed to take the average time.
+fn weighted_shards(names: &[String], durations: &HashMap<String, f64>, count: usize) -> Vec<usize> {
+    let known = names
+        .iter()
+        .filter_map(|name| durations.get(name))
+        .collect::<Vec<_>>();
+    let average = if known.is_empty() {
+        1.0
+    } else {
+        known.iter().copied().sum::<f64>() / known.len() as f64
+    };
+    let weight = |name: &String| durations.get(name).copied().unwrap_or(average);
+
+    let mut order = (0..names.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| {
+        weight(&names[b])
+            .total_cmp(&weight(&names[a]))
+            .then_with(|| names[a].cmp(&names[b]))
+    });
+    let mut totals = vec![0.0_f64; count];
+    let mut shards = vec![0; names.len()];
+    for i in order {
+        let shard = (0..count)
+            .min_by(|&a, &b| totals[a].total_cmp(&totals[b]))
+            .unwrap();
+        totals[shard] += weight(&names[i]);
+        shards[i] = shard;
+    }
+    shards
+}
+
+/// Reads the time each test took from all of `reports`, e.g. one written by each shard of the
+/// previous run. A test found in more than one report takes its time from the last of them.
+fn merge_durations(reports: &[PathBuf]) -> io::Result<HashMap<String, f64>> {
+    let mut durations = HashMap::new();
+    for report in reports {
+        durations.extend(read_durations(report)?);
+    }
+    Ok(durations)
+}
+
+/// Reads the time each test took from a `report.jsonl` written by `report::write_reports`. A
+/// test listed on more than one line of the report takes the sum of their times.
+fn read_durations(report: &Path) -> io::Result<HashMap<String, f64>> {
+    let mut durations = HashMap::new();
+    for line in BufReader::new(File::open(report)?).lines() {
+        let event = serde_json::from_str::<serde_json::Value>(&line?)?;
+        if event["type"] != "test" {
+            continue;
+        }
+        if let (Some(name), Some(time)) = (event["name"].as_str(), event["exec_time"].as_f64()) {
+            *durations.entry(name.to_owned()).or_insert(0.0) += time;
+        }
+    }
+    Ok(durations)
+}
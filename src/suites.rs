@@ -0,0 +1,245 @@
+//! Test suites described in a `compiletest.toml` file, instead of a `Config` built in Rust:
+//!
+//! ```toml
+//! # Top-level keys apply to every suite
+//! target_rustcflags = "-L target/debug -L target/debug/deps"
+//! strict_headers = true
+//!
+//! [[suite]]
+//! mode = "ui"
+//! src_base = "tests/ui"
+//! edition = "2021"
+//! normalize_stderr = [["\\d+ms", "Nms"]]
+//!
+//! [[suite]]
+//! name = "ui-nightly"
+//! mode = "ui"
+//! src_base = "tests/nightly"
+//! ```
+//!
+//! Every key is a `Config` field. A suite is named after its mode unless it has a `name`.
+//! Relative paths, like `src_base` and `build_base`, are relative to the directory of the file.
+//!
+//! Environment variables named `COMPILETEST_<FIELD>`, like `COMPILETEST_TARGET_RUSTCFLAGS`,
+//! override a field in every suite; other `COMPILETEST_*` variables are ignored. Their values
+//! are read as TOML values (`true`, `4`, `"2021"`, `["a", "b"]`), or as a string if they aren't
+//! valid TOML, e.g. `-D warnings`.
+
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::common::{Config, TestPaths};
+
+pub const CONFIG_FILE: &str = "compiletest.toml";
+
+/// A file with `DirDefaults` for the tests in its directory and subdirectories.
+pub const DEFAULTS_FILE: &str = "compiletest-defaults.toml";
+
+const ENV_PREFIX: &str = "COMPILETEST_";
+
+/// A suite from a `compiletest.toml`.
+#[derive(Clone)]
+pub struct Suite {
+    pub name: String,
+    pub config: Config,
+}
+
+/// Why `load` failed.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(PathBuf, io::Error),
+    /// The file isn't valid TOML or doesn't describe a list of `Config`s
+    Invalid(PathBuf, String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Io(ref path, ref e) => write!(f, "failed to read {}: {}", path.display(), e),
+            LoadError::Invalid(ref path, ref e) => write!(f, "invalid {}: {}", path.display(), e),
+        }
+    }
+}
+
+impl error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            LoadError::Io(_, ref e) => Some(e),
+            LoadError::Invalid(..) => None,
+        }
+    }
+}
+
+/// The contents of a `compiletest-defaults.toml`, which save repeating the same headers in every
+/// test of a directory:
+///
+/// ```toml
+/// normalize_stderr = [["thread 'main' \\(\\d+\\)", "thread 'main'"]]
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DirDefaults {
+    /// Rules like those of the `normalize-stdout` header, applied before the test's own rules
+    pub normalize_stdout: Vec<(String, String)>,
+    /// Like `normalize_stdout`, for stderr
+    pub normalize_stderr: Vec<(String, String)>,
+}
+
+impl DirDefaults {
+    /// The defaults for the test at `testpaths`: those of the `compiletest-defaults.toml` files
+    /// from its `base` down to its own directory, in that order.
+    pub fn for_test(testpaths: &TestPaths) -> Result<DirDefaults, LoadError> {
+        let mut defaults = DirDefaults::default();
+        for path in defaults_files(testpaths) {
+            let contents = match fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(LoadError::Io(path, e)),
+            };
+            let dir = toml::from_str::<DirDefaults>(&contents)
+                .map_err(|e| LoadError::Invalid(path, e.to_string()))?;
+            defaults.normalize_stdout.extend(dir.normalize_stdout);
+            defaults.normalize_stderr.extend(dir.normalize_stderr);
+        }
+        Ok(defaults)
+    }
+}
+
+/// Where the `compiletest-defaults.toml` files of the test at `testpaths` would be, whether or
+/// not they exist: in each directory from `base` down to the one containing the test. These are
+/// walked up from the test file rather than built from `relative_dir`, which starts with the name
+/// of the root for tests from `Config::extra_src_bases`.
+pub(crate) fn defaults_files(testpaths: &TestPaths) -> Vec<PathBuf> {
+    let dir = testpaths.file.parent().unwrap_or(&testpaths.base);
+    let mut files = dir
+        .ancestors()
+        .take_while(|dir| dir.starts_with(&testpaths.base))
+        .map(|dir| dir.join(DEFAULTS_FILE))
+        .collect::<Vec<_>>();
+    files.reverse();
+    files
+}
+
+/// Loads the `compiletest.toml` in the directory of the package under test
+/// (`CARGO_MANIFEST_DIR`), or in the current directory.
+pub fn load_default() -> Result<Vec<Suite>, LoadError> {
+    let dir = env::var_os("CARGO_MANIFEST_DIR").map_or_else(PathBuf::new, PathBuf::from);
+    load(&dir.join(CONFIG_FILE))
+}
+
+/// Loads the suites of a `compiletest.toml` at `path`, applying `COMPILETEST_*` overrides from
+/// the environment.
+pub fn load(path: &Path) -> Result<Vec<Suite>, LoadError> {
+    let contents = fs::read_to_string(path).map_err(|e| LoadError::Io(path.to_owned(), e))?;
+    let invalid = |e: String| LoadError::Invalid(path.to_owned(), e);
+
+    let mut common = contents
+        .parse::<toml::Table>()
+        .map_err(|e| invalid(e.to_string()))?;
+    let suites = match common.remove("suite") {
+        Some(toml::Value::Array(suites)) => suites,
+        Some(_) => return Err(invalid("`suite` must be an array of tables".to_owned())),
+        None => return Err(invalid("no `[[suite]]` found".to_owned())),
+    };
+    let overrides = env_overrides();
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    suites
+        .into_iter()
+        .map(|suite| {
+            let mut table = common.clone();
+            match suite {
+                toml::Value::Table(suite) => table.extend(suite),
+                _ => return Err(invalid("`suite` must be an array of tables".to_owned())),
+            }
+            table.extend(overrides.iter().cloned());
+            let name = match table.remove("name") {
+                Some(toml::Value::String(name)) => Some(name),
+                Some(_) => return Err(invalid("suite `name` must be a string".to_owned())),
+                None => None,
+            };
+
+            let mut config = table
+                .try_into::<Config>()
+                .map_err(|e| invalid(e.to_string()))?;
+            config.src_base = dir.join(&config.src_base);
+            config.build_base = dir.join(&config.build_base);
+            for path in config
+                .extra_src_bases
+                .iter_mut()
+                .chain(&mut config.aux_dirs)
+                .chain(&mut config.shard_weights)
+            {
+                *path = dir.join(&*path);
+            }
+            Ok(Suite {
+                name: name.unwrap_or_else(|| config.mode.to_string()),
+                config,
+            })
+        })
+        .collect()
+}
+
+/// Reads `COMPILETEST_<FIELD>=<value>` variables into `(field, value)` pairs. Variables that
+/// don't name a `Config` field, like `COMPILETEST_FORCE_STAGE0` of rustc's bootstrap, are
+/// ignored.
+fn env_overrides() -> Vec<(String, toml::Value)> {
+    let fields = config_fields();
+    let mut overrides = Vec::new();
+    for (key, value) in env::vars() {
+        let field = match key.strip_prefix(ENV_PREFIX) {
+            Some(field) => field.to_lowercase(),
+            None => continue,
+        };
+        if !fields.contains(&field.as_str()) {
+            continue;
+        }
+        let value = format!("value = {}", value)
+            .parse::<toml::Table>()
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or(toml::Value::String(value));
+        overrides.push((field, value));
+    }
+    overrides
+}
+
+/// The keys `Config` is deserialized from, as its `Deserialize` impl lists them.
+fn config_fields() -> &'static [&'static str] {
+    use serde::de::{self, Deserialize, Visitor};
+
+    /// A deserializer that only records the fields of the struct asked for.
+    struct Fields<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> de::Deserializer<'de> for Fields<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("only the fields are needed"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+            option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+            ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = Config::deserialize(Fields(&mut fields));
+    fields
+}
@@ -0,0 +1,172 @@
+//! Structured results of a test run, as returned by `run_tests_with_summary`.
+
+use std::any::Any;
+use std::error;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use crate::common::Mode;
+use crate::runtest::TestFailure;
+
+/// How a single test ended.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TestStatus {
+    Passed,
+    Failed,
+    Ignored,
+}
+
+impl fmt::Display for TestStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(
+            match *self {
+                TestStatus::Passed => "passed",
+                TestStatus::Failed => "failed",
+                TestStatus::Ignored => "ignored",
+            },
+            f,
+        )
+    }
+}
+
+/// The outcome of one test, i.e. one file (or `run-make` directory) with all its revisions.
+#[derive(Clone, Debug)]
+pub struct TestOutcome {
+    /// The name given to the test by `make_test_name`, e.g. `[ui] ui/foo.rs`
+    pub name: String,
+    pub mode: Mode,
+    /// The revision that failed, if the test has revisions and the failure could be tied to one
+    pub revision: Option<String>,
+    pub status: TestStatus,
+    /// Wall time spent running the test; zero for ignored tests
+    pub duration: Duration,
+    /// For failed tests, the error and process output printed when the test failed
+    pub message: Option<String>,
+}
+
+impl TestOutcome {
+    pub(crate) fn ignored(name: String, mode: Mode) -> Self {
+        TestOutcome {
+            name,
+            mode,
+            revision: None,
+            status: TestStatus::Ignored,
+            duration: Duration::default(),
+            message: None,
+        }
+    }
+
+    /// Builds the outcome of a test that ran, given whether it was expected to panic
+    /// (`should-fail`) and the panic payload, if it panicked.
+    pub(crate) fn finished(
+        name: String,
+        mode: Mode,
+        duration: Duration,
+        should_panic: bool,
+        panic: Option<&(dyn Any + Send)>,
+    ) -> Self {
+        let (status, revision, message) = match (panic, should_panic) {
+            (None, false) | (Some(_), true) => (TestStatus::Passed, None, None),
+            (None, true) => (
+                TestStatus::Failed,
+                None,
+                Some("test did not panic as expected".to_owned()),
+            ),
+            (Some(payload), false) => {
+                if let Some(failure) = payload.downcast_ref::<TestFailure>() {
+                    (
+                        TestStatus::Failed,
+                        failure.revision.clone(),
+                        Some(failure.message.clone()),
+                    )
+                } else if let Some(s) = payload.downcast_ref::<String>() {
+                    (TestStatus::Failed, None, Some(s.clone()))
+                } else if let Some(s) = payload.downcast_ref::<&'static str>() {
+                    (TestStatus::Failed, None, Some((*s).to_owned()))
+                } else {
+                    (TestStatus::Failed, None, None)
+                }
+            }
+        };
+
+        TestOutcome {
+            name,
+            mode,
+            revision,
+            status,
+            duration,
+            message,
+        }
+    }
+}
+
+/// Per-test outcomes of a whole run, sorted by test name.
+#[derive(Clone, Debug, Default)]
+pub struct TestSummary {
+    pub outcomes: Vec<TestOutcome>,
+    /// Wall time of the whole run
+    pub duration: Duration,
+}
+
+impl TestSummary {
+    pub fn passed(&self) -> usize {
+        self.count(TestStatus::Passed)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(TestStatus::Failed)
+    }
+
+    pub fn ignored(&self) -> usize {
+        self.count(TestStatus::Ignored)
+    }
+
+    /// `true` if no test failed.
+    pub fn is_success(&self) -> bool {
+        self.failed() == 0
+    }
+
+    /// Iterates over the failed tests.
+    pub fn failures(&self) -> impl Iterator<Item = &TestOutcome> {
+        self.outcomes
+            .iter()
+            .filter(|o| o.status == TestStatus::Failed)
+    }
+
+    fn count(&self, status: TestStatus) -> usize {
+        self.outcomes.iter().filter(|o| o.status == status).count()
+    }
+}
+
+/// An error that prevented the test run from completing.
+#[derive(Debug)]
+pub enum RunError {
+    /// Reading the test directory failed
+    Collect(io::Error),
+    /// Writing the console output or log failed
+    Io(io::Error),
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RunError::Collect(ref e) => write!(f, "failed to collect tests: {}", e),
+            RunError::Io(ref e) => write!(f, "I/O failure during tests: {}", e),
+        }
+    }
+}
+
+impl error::Error for RunError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            RunError::Collect(ref e) | RunError::Io(ref e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for RunError {
+    fn from(e: io::Error) -> Self {
+        RunError::Io(e)
+    }
+}
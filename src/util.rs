@@ -9,9 +9,12 @@
 // except according to those terms.
 
 use crate::common::Config;
+use std::collections::HashMap;
 use std::env;
 use std::ffi::OsStr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Mutex, OnceLock};
 
 /// Conversion table from triple OS name to Rust SYSNAME
 const OS_TABLE: &'static [(&'static str, &'static str)] = &[
@@ -55,6 +58,72 @@ const ARCH_TABLE: &'static [(&'static str, &'static str)] = &[
     ("wasm32", "wasm32"),
 ];
 
+/// The environments (ABIs) found as the last part of target triples, e.g. `ignore-musl`
+const ENV_NAMES: &[&str] = &[
+    "android",
+    "androideabi",
+    "eabi",
+    "eabihf",
+    "gnu",
+    "gnueabi",
+    "gnueabihf",
+    "gnux32",
+    "macabi",
+    "msvc",
+    "musl",
+    "musleabi",
+    "musleabihf",
+    "newlib",
+    "ohos",
+    "sgx",
+    "sim",
+    "uclibc",
+];
+
+/// The conditions that don't depend on the target triple
+const CONDITION_NAMES: &[&str] = &[
+    "32bit",
+    "64bit",
+    "cross-compile",
+    "gdb",
+    "lldb",
+    "pretty",
+    "test",
+    "wasm32-bare",
+];
+
+/// Every condition name of a directive like `ignore-<name>` or `only-<name>`, except stages
+/// (`stage1`) and whole targets (`x86_64-unknown-linux-gnu`, `linux-gnu`): see
+/// `is_condition_name`.
+pub fn condition_names() -> impl Iterator<Item = &'static str> {
+    let os = OS_TABLE.iter().map(|&(_, os)| os);
+    let arch = ARCH_TABLE.iter().map(|&(_, arch)| arch);
+    CONDITION_NAMES
+        .iter()
+        .copied()
+        .chain(os)
+        .chain(arch)
+        .chain(ENV_NAMES.iter().copied())
+}
+
+/// Whether `name` is something a directive like `ignore-<name>` can match: an OS, architecture,
+/// environment, pointer width, stage, debugger or (the end of) a target triple.
+pub fn is_condition_name(name: &str) -> bool {
+    if condition_names().any(|known| known == name) {
+        return true;
+    }
+    if let Some(stage) = name.strip_prefix("stage") {
+        return !stage.is_empty() && stage.bytes().all(|b| b.is_ascii_digit());
+    }
+    // A target triple, or its last parts
+    name.contains('-')
+        && name.split('-').any(|part| {
+            OS_TABLE.iter().any(|&(os, _)| part.starts_with(os))
+                || ARCH_TABLE.iter().any(|&(arch, _)| part == arch)
+                || ENV_NAMES.contains(&part)
+        })
+}
+
 pub fn matches_os(triple: &str, name: &str) -> bool {
     // For the wasm32 bare target we ignore anything also ignored on emscripten
     // and then we also recognize `wasm32-bare` as the os for the target
@@ -106,6 +175,169 @@ fn path_div() -> &'static str {
     ";"
 }
 
+/// Returns the output of `<rustc> -vV`, or `None` if it could not be run. The compiler is only
+/// asked once per path for the lifetime of the process.
+pub fn rustc_version_verbose(rustc: &Path) -> Option<String> {
+    static VERSIONS: OnceLock<Mutex<HashMap<PathBuf, Option<String>>>> = OnceLock::new();
+
+    let mut versions = VERSIONS.get_or_init(Default::default).lock().unwrap();
+    versions
+        .entry(rustc.to_path_buf())
+        .or_insert_with(|| {
+            let output = Command::new(rustc).arg("-vV").output().ok()?;
+            if !output.status.success() {
+                return None;
+            }
+            String::from_utf8(output.stdout).ok()
+        })
+        .clone()
+}
+
+/// The cargo features enabled through `CARGO_FEATURE_<NAME>` environment variables, like
+/// `serde` for `CARGO_FEATURE_SERDE`. Cargo only sets these for build scripts, not for `cargo
+/// test`, so a test harness should use `enabled_features!` instead.
+pub fn cargo_features() -> Vec<String> {
+    let mut features = env::vars()
+        .filter_map(|(name, _)| Some(name.strip_prefix("CARGO_FEATURE_")?.to_lowercase()))
+        .collect::<Vec<_>>();
+    features.sort();
+    features
+}
+
+/// Which of the given cargo features are enabled for the crate this is used in, as a
+/// `Vec<String>` for `Config::features`. In a test harness, that is the package under test, as
+/// cargo builds integration tests with the features of their package:
+///
+/// ```
+/// let mut config = compiletest_rs::Config::default();
+/// config.features = compiletest_rs::enabled_features!["serde", "std"];
+/// ```
+#[macro_export]
+macro_rules! enabled_features {
+    ($($feature:literal),* $(,)?) => {{
+        let mut features = ::std::vec::Vec::<::std::string::String>::new();
+        $(
+            if cfg!(feature = $feature) {
+                features.push(::std::string::String::from($feature));
+            }
+        )*
+        features
+    }};
+}
+
+/// Returns the lines of `rustc --print cfg` for `config.target` and `config.target_rustcflags`,
+/// like `unix` or `target_feature="avx2"`, or `None` if the compiler could not be run. The
+/// compiler is only asked once per compiler, target and flags for the lifetime of the process.
+pub fn target_cfg(config: &Config) -> Option<Vec<String>> {
+    /// Compiler, target and flags
+    type Key = (PathBuf, String, String);
+    static CFGS: OnceLock<Mutex<HashMap<Key, Option<Vec<String>>>>> = OnceLock::new();
+
+    let flags = config.target_rustcflags.clone().unwrap_or_default();
+    let mut cfgs = CFGS.get_or_init(Default::default).lock().unwrap();
+    cfgs.entry((config.rustc_path.clone(), config.target.clone(), flags))
+        .or_insert_with_key(|(rustc, target, flags)| {
+            let output = Command::new(rustc)
+                .args(["--print", "cfg", "--target", target])
+                .args(split_args(flags).ok()?)
+                .output()
+                .ok()?;
+            if !output.status.success() {
+                return None;
+            }
+            let stdout = String::from_utf8(output.stdout).ok()?;
+            Some(stdout.lines().map(str::to_owned).collect())
+        })
+        .clone()
+}
+
+/// Returns the `release` of `<rustc> -vV`, e.g. `1.80.0-nightly`.
+pub fn rustc_release(rustc: &Path) -> Option<String> {
+    rustc_version_verbose(rustc)?
+        .lines()
+        .find_map(|line| line.strip_prefix("release: "))
+        .map(str::to_owned)
+}
+
+/// `1.80.0-nightly` → `nightly`, `1.79.0` → `stable`
+pub fn release_channel(release: &str) -> &'static str {
+    if release.contains("-nightly") {
+        "nightly"
+    } else if release.contains("-beta") {
+        "beta"
+    } else if release.contains("-dev") {
+        "dev"
+    } else {
+        "stable"
+    }
+}
+
+/// Returns the first executable called `name` in a directory of `PATH`.
+pub fn find_on_path(name: &str) -> Option<PathBuf> {
+    let file_name = format!("{}{}", name, env::consts::EXE_SUFFIX);
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.is_file())
+}
+
+/// Splits `s` into arguments like a POSIX shell: at whitespace, except in `'single'` or
+/// `"double"` quotes, or after a backslash. In double quotes, a backslash only escapes `"` and
+/// `\`. Outside of quotes, a backslash only escapes whitespace, quotes and `\`, so that Windows
+/// paths like `C:\lib` need no quoting. Unlike in a shell, quotes only quote at the start of an
+/// argument: inside one they are kept, as they were before quoting was supported, so that
+/// `--cfg feature="foo"` still passes `feature="foo"`. Fails on an unterminated quote.
+pub fn split_args(s: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut arg = None::<String>;
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => args.extend(arg.take()),
+            '\\' if chars.peek().is_some_and(|&next| is_escapable(next)) => {
+                arg.get_or_insert_with(String::new)
+                    .push(chars.next().unwrap());
+            }
+            '\'' | '"' if arg.is_none() => {
+                let arg = arg.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some(end) if end == c => break,
+                        Some('\\') if c == '"' && matches!(chars.peek(), Some('"' | '\\')) => {
+                            arg.push(chars.next().unwrap())
+                        }
+                        Some(ch) => arg.push(ch),
+                        None => return Err(format!("unterminated {} quote in `{}`", c, s)),
+                    }
+                }
+            }
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+    Ok(args)
+}
+
+fn is_escapable(c: char) -> bool {
+    c.is_whitespace() || c == '\'' || c == '"' || c == '\\'
+}
+
+/// Quotes `arg`, if needed, so that `split_args` turns it back into `arg`.
+pub fn quote_arg(arg: &str) -> String {
+    let needs_quotes = arg.is_empty()
+        || arg
+            .chars()
+            .any(|c| c.is_whitespace() || c == '\'' || c == '"')
+        || arg.ends_with('\\')
+        || arg.contains("\\\\");
+    if !needs_quotes {
+        arg.to_owned()
+    } else if !arg.contains('\'') {
+        format!("'{}'", arg)
+    } else {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
 pub fn logv(config: &Config, s: String) {
     debug!("{}", s);
     if config.verbose {
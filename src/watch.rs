@@ -0,0 +1,139 @@
+//! Rerunning tests as their files change, for a quick edit-and-check loop.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use filetime::FileTime;
+
+use crate::common::{Config, TestPaths};
+use crate::summary::{RunError, TestSummary};
+
+/// How often `Watcher::watch` checks for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Keeps track of the files of each test, to run the tests whose files changed.
+pub struct Watcher {
+    config: Config,
+    rerun_all_on: Vec<PathBuf>,
+    /// The modification times of the inputs of each test (by path) when it last ran
+    runs: HashMap<PathBuf, Vec<(PathBuf, FileTime)>>,
+    /// The modification times of `rerun_all_on` when the tests last ran
+    rerun_all_mtimes: Vec<FileTime>,
+}
+
+impl Watcher {
+    pub fn new(config: &Config) -> Watcher {
+        Watcher {
+            config: Config {
+                rerun_failed: false,
+                ..config.clone()
+            },
+            rerun_all_on: vec![],
+            runs: HashMap::new(),
+            rerun_all_mtimes: vec![],
+        }
+    }
+
+    /// Also reruns every test when one of `paths` changes, e.g. when the library under test is
+    /// rebuilt.
+    pub fn rerun_all_on<I>(mut self, paths: I) -> Watcher
+    where
+        I: IntoIterator,
+        I::Item: Into<PathBuf>,
+    {
+        self.rerun_all_on.extend(paths.into_iter().map(Into::into));
+        self
+    }
+
+    /// Runs the tests that are new, or whose test file, `aux-build` files or expected output
+    /// files changed since the last call. The first call runs every test. Returns `None` if no
+    /// test needed to run.
+    pub fn run_changed(&mut self) -> Result<Option<TestSummary>, RunError> {
+        let rerun_all_mtimes = self
+            .rerun_all_on
+            .iter()
+            .map(|path| crate::mtime(path))
+            .collect();
+        let rerun_all = rerun_all_mtimes != self.rerun_all_mtimes;
+
+        let mut runs = HashMap::new();
+        let mut changed = Vec::new();
+        for paths in crate::collect_test_paths(&self.config).map_err(RunError::Collect)? {
+            let name = crate::make_test_name(&self.config, &paths).to_string();
+            if !crate::matches_filters(&self.config, &name) {
+                continue;
+            }
+            let previous = self.runs.remove(&paths.file);
+            let unchanged = previous.as_ref().is_some_and(|inputs| {
+                inputs
+                    .iter()
+                    .all(|(input, time)| crate::mtime(input) == *time)
+            });
+            if rerun_all || !unchanged {
+                changed.push((name, paths));
+            } else if let Some(inputs) = previous {
+                runs.insert(paths.file, inputs);
+            }
+        }
+        self.runs = runs;
+        self.rerun_all_mtimes = rerun_all_mtimes;
+        if changed.is_empty() {
+            return Ok(None);
+        }
+
+        let config = Config {
+            filters: changed.iter().map(|(name, _)| name.clone()).collect(),
+            filter_exact: true,
+            ..self.config.clone()
+        };
+        // Blessing rewrites expected output files, which shouldn't count as changes
+        if !config.bless {
+            self.record_inputs(&changed);
+        }
+        let summary = crate::run_tests_with_summary(&config)?;
+        if config.bless {
+            self.record_inputs(&changed);
+        }
+        Ok(Some(summary))
+    }
+
+    fn record_inputs(&mut self, tests: &[(String, TestPaths)]) {
+        for (_, paths) in tests {
+            let inputs = crate::test_inputs(&self.config, paths)
+                .into_iter()
+                .map(|input| {
+                    let time = crate::mtime(&input);
+                    (input, time)
+                })
+                .collect();
+            self.runs.insert(paths.file.clone(), inputs);
+        }
+    }
+
+    /// Runs all tests, then checks for changes twice a second and reruns the tests that
+    /// changed, as `run_changed` does. Only returns if collecting or running the tests fails.
+    pub fn watch(mut self) -> RunError {
+        loop {
+            match self.run_changed() {
+                Ok(Some(_)) => println!(
+                    "\nwatching for changes in {}",
+                    self.config.src_base.display()
+                ),
+                Ok(None) => {}
+                Err(e) => return e,
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Runs the tests of `config`, then reruns the tests whose files change, and all of them when
+/// one of the libraries of `config.dependencies` (see `Config::link_cargo_deps`) is rebuilt.
+/// Only returns if collecting or running the tests fails.
+pub fn watch(config: &Config) -> RunError {
+    Watcher::new(config)
+        .rerun_all_on(config.dependencies.externs.values().cloned())
+        .watch()
+}
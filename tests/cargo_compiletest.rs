@@ -0,0 +1,55 @@
+//! Tests for the `cargo-compiletest` binary
+
+mod test_support;
+use crate::test_support::testsuite;
+use std::process::Command;
+
+#[test]
+fn test_cargo_compiletest() {
+    let builder = testsuite("package");
+    for dir in ["src", "tests/ui", "tests/run-pass"] {
+        std::fs::create_dir_all(builder.root.join(dir)).unwrap();
+    }
+    builder.mk_file(
+        "Cargo.toml",
+        "[package]\nname = \"answer\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[workspace]\n",
+    );
+    builder.mk_file("src/lib.rs", "pub fn answer() -> u8 { 42 }\n");
+    builder.mk_file(
+        "tests/run-pass/answer.rs",
+        "extern crate answer;\nfn main() { assert_eq!(answer::answer(), 42); }\n",
+    );
+    builder.mk_file(
+        "tests/ui/mismatch.rs",
+        "fn main() { let _: u8 = answer::answer() as u16; }\n",
+    );
+
+    let cargo_compiletest = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_cargo-compiletest"))
+            .arg("compiletest")
+            .args(args)
+            .current_dir(&builder.root)
+            .output()
+            .unwrap()
+    };
+
+    // The ui test has no .stderr yet
+    let output = cargo_compiletest(&[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(output.status.code(), Some(101), "{}", stdout);
+    assert!(stdout.contains("test result: FAILED. 0 passed; 1 failed"));
+    assert!(stdout.contains("test result: ok. 1 passed; 0 failed"));
+
+    let output = cargo_compiletest(&["--mode", "ui", "--bless", "mismatch"]);
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("run-pass"));
+    assert!(builder
+        .file_contents("tests/ui/mismatch.stderr")
+        .contains("mismatched types"));
+
+    let output = cargo_compiletest(&["--edition", "2015", "--mode", "ui"]);
+    assert!(output.status.success());
+
+    let output = cargo_compiletest(&["--mode", "nonsense"]);
+    assert_eq!(output.status.code(), Some(1));
+}
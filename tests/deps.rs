@@ -0,0 +1,109 @@
+//! Tests for resolving `--extern` dependencies from cargo's build messages
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::deps::Dependencies;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+
+const METADATA: &str = r#"{
+"packages":[
+{"id":"foo 0.1.0","targets":[{"kind":["lib"],"crate_types":["lib"],"name":"foo-bar"},{"kind":["bin"],"crate_types":["bin"],"name":"tool"}]},
+{"id":"derive 0.3.0","targets":[{"kind":["proc-macro"],"crate_types":["proc-macro"],"name":"derive"}]}
+],
+"resolve":{"root":"foo 0.1.0","nodes":[
+{"id":"foo 0.1.0","deps":[
+{"name":"derive","pkg":"derive 0.3.0","dep_kinds":[{"kind":null,"target":null}]},
+{"name":"re","pkg":"regex 1.0.0","dep_kinds":[{"kind":"dev","target":null}]},
+{"name":"cc","pkg":"cc 1.0.0","dep_kinds":[{"kind":"build","target":null}]}
+]},
+{"id":"regex 1.0.0","deps":[{"name":"memchr","pkg":"memchr 2.0.0","dep_kinds":[{"kind":null,"target":null}]}]}
+]}
+}"#;
+
+#[test]
+fn test_from_cargo_messages() {
+    let messages = r#"
+{"reason":"compiler-artifact","package_id":"memchr 2.0.0","target":{"kind":["lib"],"crate_types":["lib"],"name":"memchr"},"filenames":["/t/debug/deps/libmemchr-5.rlib"],"fresh":true}
+{"reason":"compiler-artifact","package_id":"regex 1.0.0","target":{"kind":["lib"],"crate_types":["lib"],"name":"regex"},"filenames":["/t/debug/deps/libregex-6.rlib","/t/debug/deps/libregex-6.rmeta"],"fresh":true}
+{"reason":"compiler-artifact","package_id":"cc 1.0.0","target":{"kind":["lib"],"crate_types":["lib"],"name":"cc"},"filenames":["/t/debug/build/libcc-7.rlib"],"fresh":true}
+{"reason":"compiler-artifact","package_id":"checked 0.2.0","target":{"kind":["lib"],"crate_types":["lib"],"name":"checked"},"filenames":["/t/debug/deps/libchecked-2.rmeta"],"fresh":true}
+{"reason":"compiler-artifact","package_id":"derive 0.3.0","target":{"kind":["proc-macro"],"crate_types":["proc-macro"],"name":"derive"},"filenames":["/t/debug/deps/libderive-3.so"],"fresh":true}
+{"reason":"build-script-executed","package_id":"sys 0.1.0","linked_libs":["z"],"linked_paths":["native=/usr/lib/z"],"cfgs":[],"env":[],"out_dir":"/t/out"}
+{"reason":"compiler-artifact","package_id":"foo 0.1.0","target":{"kind":["lib"],"crate_types":["lib"],"name":"foo-bar"},"filenames":["/t/debug/deps/libfoo_bar-4.rlib"],"fresh":false}
+{"reason":"compiler-artifact","package_id":"foo 0.1.0","target":{"kind":["bin"],"crate_types":["bin"],"name":"tool"},"filenames":["/t/debug/tool"],"fresh":false}
+{"reason":"build-finished","success":true}
+   Compiling foo v0.1.0
+"#;
+
+    let deps = Dependencies::from_cargo_messages(METADATA.as_bytes(), messages.as_bytes()).unwrap();
+    let externs = deps
+        .externs
+        .iter()
+        .map(|(name, path)| (name.as_str(), path.to_str().unwrap()))
+        .collect::<Vec<_>>();
+    // Transitive and build dependencies aren't `--extern`s, but are still searched
+    assert_eq!(
+        externs,
+        [
+            ("derive", "/t/debug/deps/libderive-3.so"),
+            ("foo_bar", "/t/debug/deps/libfoo_bar-4.rlib"),
+            ("re", "/t/debug/deps/libregex-6.rlib"),
+        ]
+    );
+    assert_eq!(
+        deps.search_paths,
+        [
+            "dependency=/t/debug/deps",
+            "dependency=/t/debug/build",
+            "native=/usr/lib/z"
+        ]
+    );
+    assert_eq!(
+        deps.rustc_args()[..4],
+        [
+            "--extern",
+            "derive=/t/debug/deps/libderive-3.so",
+            "--extern",
+            "foo_bar=/t/debug/deps/libfoo_bar-4.rlib"
+        ]
+    );
+}
+
+#[test]
+fn test_ambiguous_dependency() {
+    let messages = r#"
+{"reason":"compiler-artifact","package_id":"regex 1.0.0","target":{"kind":["lib"],"crate_types":["lib"],"name":"regex"},"filenames":["/t/debug/deps/libregex-6.rlib"],"fresh":true}
+{"reason":"compiler-artifact","package_id":"regex 1.0.0","target":{"kind":["lib"],"crate_types":["lib"],"name":"regex"},"filenames":["/t/debug/deps/libregex-8.rlib"],"fresh":true}
+"#;
+
+    let err = Dependencies::from_cargo_messages(METADATA.as_bytes(), messages.as_bytes())
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("ambiguous dependency `re`"), "{}", err);
+}
+
+#[test]
+fn test_extern_cargo_dependency() {
+    let builder = testsuite("ui");
+    let config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_deps"),
+        dependencies: Dependencies::from_cargo_build(&[]).unwrap(),
+        ..Config::default()
+    };
+    assert!(config.dependencies.externs.contains_key("compiletest_rs"));
+    assert!(config.dependencies.externs["regex"].is_file());
+
+    builder.mk_file(
+        "uses_regex.rs",
+        "extern crate regex;\nfn main() { let _ = regex::Regex::new(\"a+\"); }\n",
+    );
+
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.passed(), 1, "{:?}", summary.failures().next());
+}
@@ -0,0 +1,54 @@
+//! Tests for `Config::detect`
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::detect::ConfigError;
+use crate::compiletest::Config;
+use crate::test_support::testsuite;
+
+#[test]
+fn test_detect_rustc() {
+    let config = Config::detect();
+    assert_eq!(config.host, Config::default().host);
+    assert_eq!(config.target, config.host);
+    assert!(["stable", "beta", "nightly", "dev"].contains(&config.channel.as_deref().unwrap()));
+    assert!(config.llvm_version.is_some());
+    assert_ne!(config.cxx, "cxx");
+}
+
+#[test]
+fn test_build_validates_mode() {
+    let builder = testsuite("codegen");
+    builder.mk_file("empty.rs", "");
+
+    let mut config = Config::detect()
+        .mode("codegen".parse().unwrap())
+        .src_base(&builder.root);
+    config.llvm_filecheck = None;
+    match config.build() {
+        Err(e @ ConfigError::MissingTool { .. }) => assert_eq!(
+            e.to_string(),
+            "codegen tests need FileCheck, which was not found; set `Config::llvm_filecheck`"
+        ),
+        _ => panic!("codegen without FileCheck was accepted"),
+    }
+
+    let config = Config::detect()
+        .mode("ui".parse().unwrap())
+        .src_base(&builder.root)
+        .build()
+        .unwrap();
+    assert_eq!(config.src_base, builder.root);
+
+    let missing = builder.root.join("missing");
+    let config = Config::detect().src_base(&missing);
+    assert!(matches!(config.build(), Err(ConfigError::SrcBase(dir)) if dir == missing));
+
+    let config = Config::detect()
+        .src_base(&builder.root)
+        .rustc_path("no-such-rustc");
+    assert!(matches!(config.build(), Err(ConfigError::Rustc(_))));
+}
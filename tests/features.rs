@@ -0,0 +1,120 @@
+//! Tests for `features`, the `needs-feature` and `ignore-feature` headers and
+//! feature-specific expected output
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::common::{
+    expected_output_path, expected_output_path_with_features, TestPaths,
+};
+use crate::compiletest::list;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+use std::path::PathBuf;
+
+#[test]
+fn test_feature_directives() {
+    let builder = testsuite("ui");
+    let config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_features"),
+        features: vec!["serde".to_owned(), "foo_bar".to_owned()],
+        ..Config::default()
+    };
+
+    builder.mk_file(
+        "serde.rs",
+        "// needs-feature: serde foo-bar\nfn main() {}\n",
+    );
+    builder.mk_file("rayon.rs", "// needs-feature: serde rayon\nfn main() {}\n");
+    builder.mk_file("no_std.rs", "// ignore-feature: std serde\nfn main() {}\n");
+    builder.mk_file("std.rs", "// ignore-feature: std\nfn main() {}\n");
+
+    let ignored = list::listed_tests(&config)
+        .unwrap()
+        .into_iter()
+        .filter(|test| test.ignored)
+        .map(|test| test.name)
+        .collect::<Vec<_>>();
+    assert_eq!(ignored, ["[ui] ui/no_std.rs", "[ui] ui/rayon.rs"]);
+}
+
+#[test]
+fn test_feature_specific_output() {
+    let builder = testsuite("ui");
+    let mut config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_features"),
+        features: vec!["serde".to_owned()],
+        ..Config::default()
+    };
+
+    builder.mk_file(
+        "warn.rs",
+        "#[warn(unused_variables)]\nfn main() {\n    let abc = 1;\n}\n",
+    );
+    builder.mk_file("warn.stderr", "");
+    builder.mk_file("warn.serde.stderr", "");
+
+    // Blessing updates the output of the enabled feature
+    config.bless = true;
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert!(summary.is_success());
+    let blessed = std::fs::read_to_string(builder.root.join("warn.serde.stderr")).unwrap();
+    assert!(blessed.contains("unused variable: `abc`"));
+    assert_eq!(
+        std::fs::read_to_string(builder.root.join("warn.stderr")).unwrap(),
+        ""
+    );
+
+    config.bless = false;
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert!(summary.is_success());
+
+    // Without the feature, `warn.stderr` is expected
+    config.features = vec![];
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.failed(), 1);
+
+    // With output for two enabled features, the first feature's is used
+    config.features = vec!["serde".to_owned(), "std".to_owned()];
+    builder.mk_file("warn.std.stderr", "");
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert!(summary.is_success());
+    config.features.reverse();
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.failed(), 1);
+
+    // `expected_output_path` ignores the features
+    let testpaths = TestPaths {
+        file: builder.root.join("warn.rs"),
+        base: builder.root.clone(),
+        relative_dir: PathBuf::new(),
+    };
+    assert_eq!(
+        expected_output_path(&testpaths, None, "stderr"),
+        builder.root.join("warn.stderr")
+    );
+    assert_eq!(
+        expected_output_path_with_features(&config, &testpaths, None, "stderr"),
+        builder.root.join("warn.std.stderr")
+    );
+}
+
+#[test]
+fn test_enabled_features() {
+    let features = compiletest::enabled_features!["tmp", "stable"];
+    let expected = [
+        ("tmp", cfg!(feature = "tmp")),
+        ("stable", cfg!(feature = "stable")),
+    ]
+    .iter()
+    .filter(|&&(_, enabled)| enabled)
+    .map(|&(feature, _)| feature)
+    .collect::<Vec<_>>();
+    assert_eq!(features, expected);
+}
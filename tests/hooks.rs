@@ -0,0 +1,82 @@
+//! Tests for `Config::hooks`
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::hooks::{Hook, HookCx, ProcessKind};
+use crate::compiletest::runtest::ProcRes;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Default)]
+struct Recorder {
+    processes: Mutex<Vec<(String, ProcessKind)>>,
+}
+
+impl Hook for Recorder {
+    fn before(&self, cx: &HookCx, command: &mut Command) {
+        match cx.kind {
+            ProcessKind::Compile => command.args(["--cfg", "hooked"]),
+            ProcessKind::Run => command.env("HOOKED", "1"),
+        };
+    }
+
+    fn after(&self, cx: &HookCx, proc_res: &mut ProcRes, _duration: Duration) {
+        let name = cx.paths.file.file_name().unwrap().to_str().unwrap();
+        self.processes
+            .lock()
+            .unwrap()
+            .push((name.to_owned(), cx.kind));
+        if cx.kind == ProcessKind::Compile {
+            *proc_res.stderr_mut() = proc_res
+                .stderr()
+                .lines()
+                .filter(|line| line.starts_with("warning: unused"))
+                .map(|line| format!("{}\n", line.replace("abc", "XYZ")))
+                .collect();
+        }
+    }
+}
+
+#[test]
+fn test_hooks() {
+    let recorder = Arc::new(Recorder::default());
+
+    let builder = testsuite("run-pass");
+    let mut config = Config {
+        mode: "run-pass".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_hooks"),
+        ..Config::default()
+    };
+    config.hooks.push(recorder.clone());
+    builder.mk_file(
+        "env.rs",
+        "#[cfg(hooked)]\nfn main() {\n    assert_eq!(std::env::var(\"HOOKED\").unwrap(), \"1\");\n}\n",
+    );
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.passed(), 1, "{:?}", summary.failures().next());
+    assert_eq!(
+        *recorder.processes.lock().unwrap(),
+        [
+            ("env.rs".to_owned(), ProcessKind::Compile),
+            ("env.rs".to_owned(), ProcessKind::Run)
+        ]
+    );
+
+    let builder = testsuite("ui");
+    config.mode = "ui".parse().expect("Invalid mode");
+    config.src_base = builder.root.clone();
+    builder.mk_file(
+        "warn.rs",
+        "// compile-flags: --error-format human\n#[warn(unused_variables)]\nfn main() {\n    let abc = 1;\n}\n",
+    );
+    builder.mk_file("warn.stderr", "warning: unused variable: `XYZ`\n");
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.passed(), 1, "{:?}", summary.failures().next());
+}
@@ -0,0 +1,90 @@
+//! Tests for `list_tests` and the `list` option
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::list::{self, ListFormat};
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+
+#[test]
+fn test_list_tests() {
+    let builder = testsuite("ui");
+    let config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_list"),
+        list: Some(ListFormat::Json),
+        ..Config::default()
+    };
+
+    for dir in ["auxiliary", "nested", "hidden"] {
+        std::fs::create_dir_all(builder.root.join(dir)).unwrap();
+    }
+    builder.mk_file("plain.rs", "fn main() { let _: u8 = \"\"; }\n");
+    builder.mk_file("revs.rs", "// revisions: foo bar\nfn main() {}\n");
+    builder.mk_file("skip.rs", "// ignore-test\nfn main() {}\n");
+    builder.mk_file("auxiliary/helper.rs", "pub fn helper() {}\n");
+    builder.mk_file("nested/inner.rs", "fn main() {}\n");
+    builder.mk_file("hidden/compiletest-ignore-dir", "");
+    builder.mk_file("hidden/hidden.rs", "fn main() {}\n");
+
+    let tests = list::listed_tests(&config).unwrap();
+    let names = tests.iter().map(|t| t.name.as_str()).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "[ui] ui/nested/inner.rs",
+            "[ui] ui/plain.rs",
+            "[ui] ui/revs.rs",
+            "[ui] ui/skip.rs"
+        ]
+    );
+    assert_eq!(tests[2].revisions, ["foo", "bar"]);
+    assert!(tests[3].ignored && !tests[2].ignored);
+
+    let mut text = Vec::new();
+    list::write_list(&tests, ListFormat::Text, &mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.contains("[ui] ui/revs.rs (ui) [revisions: foo, bar]\n"));
+    assert!(text.contains("[ui] ui/skip.rs (ui) ignored\n"));
+    assert!(text.ends_with("\n4 tests, 1 ignored\n"));
+
+    let mut json = Vec::new();
+    list::write_list(&tests, ListFormat::Json, &mut json).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    assert_eq!(
+        json.lines().nth(2).unwrap(),
+        r#"{"name":"[ui] ui/revs.rs","mode":"ui","revisions":["foo","bar"],"ignored":false}"#
+    );
+
+    // Listing doesn't run anything, so the failing test doesn't fail the run
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert!(summary.outcomes.is_empty());
+}
+
+#[test]
+fn test_list_invalid_directives() {
+    let builder = testsuite("ui");
+    let config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_list_invalid"),
+        strict_headers: true,
+        ..Config::default()
+    };
+
+    builder.mk_file("skip.rs", "//@ ignore-test\nfn main() {}\n");
+    builder.mk_file(
+        "typo.rs",
+        "//@ ignore-test\n//@ compile-flag: -O\nfn main() {}\n",
+    );
+
+    // Tests with invalid directives run, and fail, even if they are ignored
+    let tests = list::listed_tests(&config).unwrap();
+    assert!(tests[0].ignored && !tests[1].ignored);
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!((summary.ignored(), summary.failed()), (1, 1));
+}
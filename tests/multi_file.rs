@@ -0,0 +1,110 @@
+//! Tests for tests made of a directory with several files
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::list;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+use std::fs;
+
+#[test]
+fn test_multi_file() {
+    let builder = testsuite("ui");
+    let mut config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_multi_file"),
+        ..Config::default()
+    };
+
+    for dir in ["warn", "warn/nested", "lib", "plain"] {
+        fs::create_dir_all(builder.root.join(dir)).unwrap();
+    }
+    builder.mk_file(
+        "warn/main.rs",
+        "// multi-file\n\nmod nested;\n\nfn main() {\n    nested::f();\n}\n",
+    );
+    builder.mk_file("warn/nested/mod.rs", "mod inner;\n\npub fn f() {}\n");
+    builder.mk_file(
+        "warn/nested/inner.rs",
+        "#[warn(unused_variables)]\npub fn g() {\n    let abc = 1;\n}\n",
+    );
+    // A `compiletest.toml` marks the directory too, and `lib.rs` is built as a library
+    builder.mk_file("lib/compiletest.toml", "");
+    builder.mk_file("lib/lib.rs", "pub mod a;\n");
+    builder.mk_file("lib/a.rs", "pub fn f() {}\n");
+    // Without a marker, the files of a directory are separate tests
+    builder.mk_file("plain/main.rs", "fn main() {}\n");
+
+    let names = list::listed_tests(&config)
+        .unwrap()
+        .into_iter()
+        .map(|test| test.name)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["[ui] ui/lib", "[ui] ui/plain/main.rs", "[ui] ui/warn"]
+    );
+
+    config.bless = true;
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.passed(), 3, "{:?}", summary.failures().next());
+    let stderr = builder.file_contents("warn.stderr");
+    assert!(stderr.contains("unused variable: `abc`"), "{}", stderr);
+    assert!(
+        stderr.contains("--> $DIR/warn/nested/inner.rs:3:9"),
+        "{}",
+        stderr
+    );
+    assert!(!builder.root.join("lib.stderr").exists());
+
+    config.bless = false;
+    config.skip_up_to_date = true;
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert!(summary.is_success(), "{:?}", summary.failures().next());
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.ignored(), 3);
+
+    // Editing a module makes the test out of date, so it runs again instead of being skipped
+    builder.mk_file(
+        "warn/nested/inner.rs",
+        "#[warn(unused_variables)]\npub fn g() {\n    let xyz = 1;\n}\n",
+    );
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!((summary.ignored(), summary.failed()), (2, 1));
+    let failure = summary.failures().next().unwrap();
+    assert_eq!(failure.name, "[ui] ui/warn");
+}
+
+#[test]
+fn test_multi_file_library_is_not_run() {
+    let builder = testsuite("run-pass");
+    let config = Config {
+        mode: "run-pass".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_multi_file_lib"),
+        ..Config::default()
+    };
+
+    for dir in ["lib", "bin"] {
+        fs::create_dir_all(builder.root.join(dir)).unwrap();
+    }
+    builder.mk_file("lib/lib.rs", "// multi-file\n\npub fn f() {}\n");
+    builder.mk_file(
+        "bin/lib.rs",
+        "// multi-file\n// compile-flags: --crate-type bin\n\nfn main() {}\n",
+    );
+
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.passed(), 1, "{:?}", summary.failures().next());
+    let failure = summary.failures().next().unwrap();
+    assert_eq!(failure.name, "[run-pass] run-pass/lib");
+    assert!(failure
+        .message
+        .as_ref()
+        .unwrap()
+        .contains("are built as libraries and can't be run"));
+}
@@ -0,0 +1,65 @@
+//! Tests for the `needs-*` headers
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::list;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+
+fn ignored(config: &Config) -> Vec<String> {
+    list::listed_tests(config)
+        .unwrap()
+        .into_iter()
+        .filter(|test| test.ignored)
+        .map(|test| test.name)
+        .collect()
+}
+
+#[test]
+fn test_needs_directives() {
+    let builder = testsuite("ui");
+    let mut config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_needs"),
+        ..Config::default()
+    };
+
+    for need in [
+        "unwind",
+        "proc-macro",
+        "run-enabled",
+        "dynamic-linking",
+        "sanitizer-nonexistent",
+    ] {
+        builder.mk_file(
+            &format!("{}.rs", need),
+            &format!("// needs-{}\nfn main() {{}}\n", need),
+        );
+    }
+
+    assert_eq!(ignored(&config), ["[ui] ui/sanitizer-nonexistent.rs"]);
+
+    config.target_rustcflags = Some("-C panic=abort".to_owned());
+    assert_eq!(
+        ignored(&config),
+        ["[ui] ui/sanitizer-nonexistent.rs", "[ui] ui/unwind.rs"]
+    );
+
+    // wasm32 programs can't run without `nodejs`, and wasm32 only has cdylibs. Proc macros are
+    // built for the host, so they still work.
+    config.target_rustcflags = None;
+    config.target = "wasm32-unknown-unknown".to_owned();
+    assert_eq!(
+        ignored(&config),
+        [
+            "[ui] ui/dynamic-linking.rs",
+            "[ui] ui/run-enabled.rs",
+            "[ui] ui/sanitizer-nonexistent.rs",
+            "[ui] ui/unwind.rs"
+        ]
+    );
+}
@@ -0,0 +1,137 @@
+//! Tests for escapes, captures and regex flags in `normalize-stderr` and `normalize-stdout`, and
+//! for the rules of `Config`, `normalize_presets` and `compiletest-defaults.toml`
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::common::NormalizePreset;
+use crate::compiletest::suites::DEFAULTS_FILE;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+use std::fs;
+
+#[test]
+fn test_normalization_rules() {
+    let builder = testsuite("ui");
+    let config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_normalize"),
+        // Reads the headers while collecting the tests, which mustn't abort the run
+        skip_up_to_date: true,
+        ..Config::default()
+    };
+
+    builder.mk_file(
+        "rules.rs",
+        r#"// normalize-stderr-test: "\n.*"s -> "\n"
+// normalize-stderr-test: "^warning: unused variable: `(\w+)`$"m -> "unused \"$1\"\t\\"
+// normalize-stderr-test: "unused \"abc\"" -> "unused \"xyz\""
+#[warn(unused_variables)]
+fn main() {
+    let abc = 1;
+}
+"#,
+    );
+    builder.mk_file("rules.stderr", "unused \"xyz\"\t\\\n");
+    builder.mk_file(
+        "bad_flag.rs",
+        "// normalize-stderr-test: \"x\"q -> \"y\"\nfn main() {}\n",
+    );
+
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.passed(), 1, "{:?}", summary.failures().next());
+    let failure = summary.failures().next().unwrap();
+    assert_eq!(failure.name, "[ui] ui/bad_flag.rs");
+    assert!(failure
+        .message
+        .as_ref()
+        .unwrap()
+        .contains("unknown regex flag `q`"));
+}
+
+#[test]
+fn test_suite_wide_rules() {
+    let builder = testsuite("ui");
+    let config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_normalize"),
+        normalize_stderr: vec![("abc".to_owned(), "b1".to_owned())],
+        normalize_presets: vec![NormalizePreset::Hex],
+        bless: true,
+        ..Config::default()
+    };
+
+    // Each set of rules sees the output of the ones before
+    fs::create_dir_all(builder.root.join("nested")).unwrap();
+    builder.mk_file(DEFAULTS_FILE, "normalize_stderr = [['b1', 'b2']]\n");
+    builder.mk_file(
+        "nested/compiletest-defaults.toml",
+        "normalize_stderr = [['b2', 'b3']]\n",
+    );
+    builder.mk_file(
+        "nested/warn.rs",
+        "// normalize-stderr-test: \"b3\" -> \"b4\"\n#[warn(unused_variables)]\nfn main() {\n    let abc = 0x1F;\n}\n",
+    );
+
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert!(summary.is_success(), "{:?}", summary.failures().next());
+    let stderr = fs::read_to_string(builder.root.join("nested/warn.stderr")).unwrap();
+    assert!(stderr.contains("let b4 = $HEX;"), "{}", stderr);
+
+    builder.mk_file(DEFAULTS_FILE, "normalize_stdrr = []\n");
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert!(!summary.is_success());
+
+    // Other tests reuse the directory
+    fs::remove_file(builder.root.join(DEFAULTS_FILE)).unwrap();
+    fs::remove_file(builder.root.join("nested").join(DEFAULTS_FILE)).unwrap();
+}
+
+#[test]
+fn test_defaults_under_extra_src_base() {
+    let builder = testsuite("ui");
+    let extra = builder.root.parent().unwrap().join("extra_defaults");
+    fs::create_dir_all(extra.join("nested")).unwrap();
+    let config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        extra_src_bases: vec![extra.clone()],
+        build_base: global_root().join("build_base_normalize_extra"),
+        bless: true,
+        ..Config::default()
+    };
+
+    fs::write(
+        extra.join(DEFAULTS_FILE),
+        "normalize_stderr = [['abc', 'b1']]\n",
+    )
+    .unwrap();
+    fs::write(
+        extra.join("nested").join(DEFAULTS_FILE),
+        "normalize_stderr = [['b1', 'b2']]\n",
+    )
+    .unwrap();
+    fs::write(
+        extra.join("nested/warn.rs"),
+        "#[warn(unused_variables)]\nfn main() {\n    let abc = 1;\n}\n",
+    )
+    .unwrap();
+
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert!(summary.is_success(), "{:?}", summary.failures().next());
+    let stderr = fs::read_to_string(extra.join("nested/warn.stderr")).unwrap();
+    assert!(stderr.contains("let b2 = 1;"), "{}", stderr);
+}
+
+#[test]
+fn test_rustc_version_preset() {
+    let config = Config::default();
+    let (from, to) = NormalizePreset::RustcVersion.rule(&config).unwrap();
+    let version = compiletest::util::rustc_version_verbose(&config.rustc_path).unwrap();
+    assert!(version.contains(&format!("release: {}\n", from.replace('\\', ""))));
+    assert_eq!(to, "$$RUSTC_VERSION");
+}
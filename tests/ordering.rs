@@ -0,0 +1,87 @@
+//! Tests for the `fail_fast` and `shuffle_seed` options
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+
+fn first_failure(config: &Config) -> String {
+    let summary = compiletest::run_tests_with_summary(config).unwrap();
+    assert_eq!(summary.failed(), 1);
+    let failure = summary.failures().next().unwrap();
+    failure.name.clone()
+}
+
+#[test]
+fn test_fail_fast() {
+    let builder = testsuite("ui");
+    let config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_fail_fast"),
+        test_threads: Some(1),
+        fail_fast: true,
+        logfile: Some(global_root().join("fail_fast.log")),
+        ..Config::default()
+    };
+
+    builder.mk_file("a.rs", "fn main() {}\n");
+    builder.mk_file("b.rs", "fn main() { let _: u8 = \"\"; }\n");
+    builder.mk_file("c.rs", "fn main() { let _: u8 = \"\"; }\n");
+
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!((summary.passed(), summary.failed()), (1, 1));
+    assert_eq!(summary.failures().next().unwrap().name, "[ui] ui/b.rs");
+
+    // The harness doesn't report the test that was skipped as passed
+    let log = std::fs::read_to_string(config.logfile.as_ref().unwrap()).unwrap();
+    assert!(log.contains("ok [ui] ui/a.rs"), "{}", log);
+    assert!(log.contains("failed [ui] ui/c.rs"), "{}", log);
+}
+
+#[test]
+fn test_shuffle_seed() {
+    let builder = testsuite("ui");
+    let mut config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_shuffle"),
+        test_threads: Some(1),
+        fail_fast: true,
+        ..Config::default()
+    };
+
+    for name in ["a.rs", "b.rs", "c.rs", "d.rs"] {
+        builder.mk_file(name, "fn main() { let _: u8 = \"\"; }\n");
+    }
+
+    // With one thread and fail-fast, only the first test in the shuffled order runs
+    let mut firsts = Vec::new();
+    for seed in 0..8 {
+        config.shuffle_seed = Some(seed);
+        let first = first_failure(&config);
+        assert_eq!(first_failure(&config), first, "seed {} is not stable", seed);
+        firsts.push(first);
+    }
+    firsts.sort();
+    firsts.dedup();
+    assert!(firsts.len() > 1, "all seeds ran {:?} first", firsts);
+    assert!(firsts.iter().all(|name| name.starts_with("[ui] ui/")));
+
+    // Shuffling keeps the names, so exact filters still apply
+    config.filters = vec![firsts[1].clone()];
+    config.filter_exact = true;
+    config.fail_fast = false;
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.failed(), 1);
+    assert_eq!(summary.failures().next().unwrap().name, firsts[1]);
+
+    // Every test runs exactly once when several run in parallel
+    config.filters.clear();
+    config.test_threads = Some(3);
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.failed(), 4);
+}
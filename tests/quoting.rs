@@ -0,0 +1,83 @@
+//! Tests for shell-style quoting in flags and `runtool`
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::util::{quote_arg, split_args};
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+
+#[test]
+fn test_split_args() {
+    assert_eq!(
+        split_args(r#" a  'b c' "d \"e\" \x" f\ g C:\lib '' "#).unwrap(),
+        ["a", "b c", r#"d "e" \x"#, "f g", r"C:\lib", ""]
+    );
+    assert!(split_args("'unterminated").is_err());
+    // Quotes inside an argument are kept
+    assert_eq!(
+        split_args(r#"--cfg feature="foo" it's"#).unwrap(),
+        ["--cfg", r#"feature="foo""#, "it's"]
+    );
+    for arg in ["a", "b c", "it's", r#"say "hi""#, r"C:\lib", r"dir\", ""] {
+        assert_eq!(split_args(&quote_arg(arg)).unwrap(), [arg]);
+    }
+}
+
+#[test]
+fn test_quoted_flags() {
+    let builder = testsuite("run-pass");
+    let mut config = Config {
+        mode: "run-pass".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_quoting"),
+        target_rustcflags: Some(r#"--cfg 'from_config="a b"'"#.to_owned()),
+        // Reads the headers while collecting the tests, which mustn't abort the run
+        skip_up_to_date: true,
+        ..Config::default()
+    };
+
+    builder.mk_file(
+        "quoted.rs",
+        r#"// compile-flags: --cfg 'from_header="c d"' --cfg "escaped=\"e\"" --cfg legacy="f"
+// run-flags: 'first arg' second\ arg
+fn main() {
+    assert!(cfg!(from_config = "a b"));
+    assert!(cfg!(from_header = "c d"));
+    assert!(cfg!(escaped = "e"));
+    assert!(cfg!(legacy = "f"));
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    assert_eq!(args, ["first arg", "second arg"]);
+}
+"#,
+    );
+    builder.mk_file(
+        "unterminated.rs",
+        "// compile-flags: --cfg 'oops\nfn main() {}\n",
+    );
+
+    // A runtool in a directory with a space in its name
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = builder.root.join("run tool");
+        std::fs::create_dir_all(&dir).unwrap();
+        let tool = dir.join("wrap.sh");
+        std::fs::write(&tool, "#!/bin/sh\nshift\nexec \"$@\"\n").unwrap();
+        std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+        config.runtool = Some(format!("{} --ignored", quote_arg(tool.to_str().unwrap())));
+    }
+
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.passed(), 1, "{:?}", summary.failures().next());
+    let failure = summary.failures().next().unwrap();
+    assert_eq!(failure.name, "[run-pass] run-pass/unterminated.rs");
+    assert!(failure
+        .message
+        .as_ref()
+        .unwrap()
+        .contains("unterminated ' quote"));
+}
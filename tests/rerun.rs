@@ -0,0 +1,81 @@
+//! Tests for `rerun_failed`
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::report;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+use std::fs;
+
+#[test]
+fn test_rerun_failed() {
+    let builder = testsuite("ui");
+    let mut config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_rerun"),
+        rerun_failed: true,
+        ..Config::default()
+    };
+    fs::create_dir_all(&config.build_base).unwrap();
+    let _ = fs::remove_file(report::failed_tests_path(&config));
+
+    let warning = "#[warn(unused_variables)]\nfn main() {\n    let abc = 1;\n}\n";
+    builder.mk_file("pass.rs", "fn main() {}\n");
+    builder.mk_file("fail1.rs", warning);
+    builder.mk_file("fail2.rs", warning);
+
+    // Without a list of failures, every test runs
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!((summary.passed(), summary.failed()), (1, 2));
+    assert_eq!(
+        fs::read_to_string(report::failed_tests_path(&config)).unwrap(),
+        "[ui] ui/fail1.rs\n[ui] ui/fail2.rs\n"
+    );
+
+    // Tests that are filtered out stay in the list
+    builder.mk_file("fail1.rs", "fn main() {}\n");
+    config.filters = vec!["fail1".to_owned()];
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!((summary.passed(), summary.failed()), (1, 0));
+    let failed = report::read_failed_tests(&config).unwrap().unwrap();
+    assert_eq!(failed.into_iter().collect::<Vec<_>>(), ["[ui] ui/fail2.rs"]);
+
+    config.filters = vec![];
+    builder.mk_file("fail2.rs", "fn main() {}\n");
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.outcomes.len(), 1);
+    assert_eq!(summary.outcomes[0].name, "[ui] ui/fail2.rs");
+    assert!(summary.is_success());
+
+    // Nothing failed last time, so nothing runs
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert!(summary.outcomes.is_empty());
+}
+
+#[test]
+fn test_failures_are_recorded_without_rerun_failed() {
+    let builder = testsuite("ui");
+    let mut config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_rerun_record"),
+        ..Config::default()
+    };
+    fs::create_dir_all(&config.build_base).unwrap();
+    let _ = fs::remove_file(report::failed_tests_path(&config));
+
+    builder.mk_file("pass.rs", "fn main() {}\n");
+    builder.mk_file("fail.rs", "fn main() { let _: u8 = \"\"; }\n");
+
+    // A normal run records its failures, so the first run with `rerun_failed` only reruns them
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!((summary.passed(), summary.failed()), (1, 1));
+    config.rerun_failed = true;
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.outcomes.len(), 1);
+    assert_eq!(summary.outcomes[0].name, "[ui] ui/fail.rs");
+}
@@ -0,0 +1,77 @@
+//! Tests for user-defined modes
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::common::Mode;
+use crate::compiletest::runner::{self, RunnerCx, TestRunner};
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+use std::process::Command;
+
+/// Compiles the test, runs it and compares its stdout to `<test>.run`.
+struct RunOutput;
+
+impl TestRunner for RunOutput {
+    fn run(&self, cx: &RunnerCx) {
+        let proc_res = cx.compile();
+        if !proc_res.status().success() {
+            cx.fatal_proc_rec("compilation failed!", &proc_res);
+        }
+        let proc_res = cx.run(Command::new(cx.exe_path()));
+        let stdout = cx.normalize_output(proc_res.stdout(), &cx.props().normalize_stdout);
+        if cx.compare_output("run", &stdout) > 0 {
+            cx.fatal_proc_rec("run output differs", &proc_res);
+        }
+    }
+
+    fn output_kinds(&self) -> &[&str] {
+        &["run"]
+    }
+}
+
+#[test]
+fn test_custom_mode() {
+    let mode = runner::register_mode("run-output", RunOutput);
+    assert_eq!("run-output".parse::<Mode>(), Ok(mode));
+    assert_eq!(mode.to_string(), "run-output");
+    assert!("not-registered".parse::<Mode>().is_err());
+
+    let builder = testsuite("run-output");
+    let mut config = Config {
+        mode,
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_runner"),
+        ..Config::default()
+    };
+    builder.mk_file("hello.rs", "fn main() {\n    println!(\"hello\");\n}\n");
+    builder.mk_file("hello.run", "hello\n");
+    builder.mk_file("dir.rs", "fn main() {\n    println!(\"{}\", file!());\n}\n");
+    builder.mk_file("dir.run", "$DIR/dir.rs\n");
+    builder.mk_file("wrong.rs", "fn main() {\n    println!(\"world\");\n}\n");
+    builder.mk_file("wrong.run", "hello\n");
+
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.passed(), 2);
+    let failure = summary.failures().next().unwrap();
+    assert_eq!(failure.name, "[run-output] run-output/wrong.rs");
+    assert!(failure
+        .message
+        .as_ref()
+        .unwrap()
+        .contains("run output differs"));
+
+    config.bless = true;
+    assert!(compiletest::run_tests_with_summary(&config)
+        .unwrap()
+        .is_success());
+    assert_eq!(builder.file_contents("wrong.run"), "world\n");
+}
+
+#[test]
+#[should_panic(expected = "`ui` is a built-in mode")]
+fn test_register_builtin_mode() {
+    runner::register_mode("ui", RunOutput);
+}
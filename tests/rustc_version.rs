@@ -0,0 +1,76 @@
+//! Tests for the `min-rustc-version`, `ignore-channel` and `only-channel` headers
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::list;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+
+#[test]
+fn test_toolchain_directives() {
+    let builder = testsuite("ui");
+    let config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_rustc_version"),
+        channel: Some("nightly".to_owned()),
+        ..Config::default()
+    };
+
+    builder.mk_file("old_enough.rs", "// min-rustc-version: 1.0\nfn main() {}\n");
+    builder.mk_file(
+        "too_new.rs",
+        "// min-rustc-version: 999.0.1\nfn main() {}\n",
+    );
+    builder.mk_file(
+        "not_nightly.rs",
+        "// ignore-channel: nightly\nfn main() {}\n",
+    );
+    builder.mk_file("not_stable.rs", "// ignore-channel: stable\nfn main() {}\n");
+    builder.mk_file("only_stable.rs", "// only-channel: stable\nfn main() {}\n");
+    builder.mk_file(
+        "only_nightly.rs",
+        "// only-channel: beta nightly\nfn main() {}\n",
+    );
+
+    let ignored = list::listed_tests(&config)
+        .unwrap()
+        .into_iter()
+        .filter(|test| test.ignored)
+        .map(|test| test.name)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ignored,
+        [
+            "[ui] ui/not_nightly.rs",
+            "[ui] ui/only_stable.rs",
+            "[ui] ui/too_new.rs"
+        ]
+    );
+}
+
+#[test]
+fn test_malformed_min_rustc_version_fails_the_test() {
+    let builder = testsuite("run-pass");
+    let config = Config {
+        mode: "run-pass".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_malformed_rustc_version"),
+        ..Config::default()
+    };
+
+    builder.mk_file("pass.rs", "// min-rustc-version: 1.0\nfn main() {}\n");
+    builder.mk_file("malformed.rs", "// min-rustc-version: soon\nfn main() {}\n");
+
+    let summary = compiletest::run_tests_with_summary(&config).expect("run failed");
+    assert_eq!(summary.passed(), 1);
+    let failure = summary.failures().next().expect("test should have failed");
+    assert_eq!(failure.name, "[run-pass] run-pass/malformed.rs");
+    let message = failure.message.as_ref().unwrap();
+    assert!(message.contains(
+        "malformed min-rustc-version directive: expected a version like `1.78`, found `soon`"
+    ));
+}
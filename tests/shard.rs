@@ -0,0 +1,103 @@
+//! Tests for `shard_index`, `shard_count` and `shard_weights`
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::list;
+use crate::compiletest::shard::stable_hash;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+use std::collections::HashSet;
+
+fn shard(config: &Config, index: usize) -> Vec<String> {
+    let config = Config {
+        shard_index: index,
+        ..config.clone()
+    };
+    list::listed_tests(&config)
+        .unwrap()
+        .into_iter()
+        .map(|test| test.name)
+        .collect()
+}
+
+#[test]
+fn test_stable_hash() {
+    assert_eq!(stable_hash(""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(stable_hash("a"), 0xaf63_dc4c_8601_ec8c);
+}
+
+#[test]
+fn test_shards() {
+    let builder = testsuite("ui");
+    for i in 0..20 {
+        builder.mk_file(&format!("t{}.rs", i), "fn main() {}\n");
+    }
+    let mut config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_shard"),
+        shard_count: 3,
+        ..Config::default()
+    };
+
+    let shards = (0..3).map(|i| shard(&config, i)).collect::<Vec<_>>();
+    assert_eq!(shards.iter().map(Vec::len).sum::<usize>(), 20);
+    let all = shards.iter().flatten().collect::<HashSet<_>>();
+    assert_eq!(all.len(), 20);
+    for (i, tests) in shards.iter().enumerate() {
+        for name in tests {
+            assert_eq!(stable_hash(name) % 3, i as u64);
+        }
+    }
+
+    config.shard_index = 3;
+    assert!(list::listed_tests(&config).is_err());
+}
+
+#[test]
+fn test_weighted_shards() {
+    let builder = testsuite("ui");
+    for name in ["a", "b", "c", "d", "e"] {
+        builder.mk_file(&format!("{}.rs", name), "fn main() {}\n");
+    }
+    // The reports of the two shards of a previous run
+    builder.mk_file(
+        "report0.jsonl",
+        r#"{"type":"test","name":"[ui] ui/a.rs","mode":"ui","revision":null,"status":"ok","exec_time":10.0,"output":null}
+{"type":"suite","passed":1,"failed":0,"ignored":0,"exec_time":10.0}
+"#,
+    );
+    builder.mk_file(
+        "report1.jsonl",
+        r#"{"type":"test","name":"[ui] ui/b.rs","mode":"ui","revision":null,"status":"ok","exec_time":1.0,"output":null}
+{"type":"test","name":"[ui] ui/c.rs","mode":"ui","revision":null,"status":"ok","exec_time":1.0,"output":null}
+{"type":"suite","passed":2,"failed":0,"ignored":0,"exec_time":2.0}
+"#,
+    );
+    let config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_shard"),
+        shard_count: 2,
+        shard_weights: vec![
+            builder.root.join("report0.jsonl"),
+            builder.root.join("report1.jsonl"),
+        ],
+        ..Config::default()
+    };
+
+    // `d` and `e` aren't in the report, so they are assumed to take the average of 4s
+    assert_eq!(shard(&config, 0), ["[ui] ui/a.rs"]);
+    assert_eq!(
+        shard(&config, 1),
+        [
+            "[ui] ui/b.rs",
+            "[ui] ui/c.rs",
+            "[ui] ui/d.rs",
+            "[ui] ui/e.rs"
+        ]
+    );
+}
@@ -0,0 +1,73 @@
+//! Tests for `extra_src_bases` and `aux_dirs`
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::list;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+use std::fs;
+
+#[test]
+fn test_extra_src_bases() {
+    let builder = testsuite("run-pass");
+    let root = builder.root.parent().unwrap();
+    for dir in [
+        "run-pass/auxiliary",
+        "extra/nested",
+        "shared",
+        "other/run-pass",
+    ] {
+        fs::create_dir_all(root.join(dir)).unwrap();
+    }
+    let mut config = Config {
+        mode: "run-pass".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        extra_src_bases: vec![root.join("extra")],
+        aux_dirs: vec![root.join("shared")],
+        build_base: global_root().join("build_base_src_bases"),
+        ..Config::default()
+    };
+
+    let uses_helper = "// aux-build: helper.rs\nextern crate helper;\nfn main() {\n    assert_eq!(helper::VALUE, 1);\n}\n";
+    builder.mk_file("a.rs", uses_helper);
+    fs::write(root.join("extra/a.rs"), uses_helper).unwrap();
+    fs::write(root.join("extra/nested/b.rs"), "fn main() {}\n").unwrap();
+    fs::write(root.join("shared/helper.rs"), "pub const VALUE: u8 = 2;\n").unwrap();
+    // `auxiliary` next to the test takes precedence over `aux_dirs`
+    builder.mk_file("auxiliary/helper.rs", "pub const VALUE: u8 = 1;\n");
+    fs::write(
+        root.join("extra/c.rs"),
+        "// aux-build: helper.rs\nextern crate helper;\nfn main() {\n    assert_eq!(helper::VALUE, 2);\n}\n",
+    )
+    .unwrap();
+
+    let names = list::listed_tests(&config)
+        .unwrap()
+        .into_iter()
+        .map(|test| test.name)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        [
+            "[run-pass] extra/a.rs",
+            "[run-pass] extra/c.rs",
+            "[run-pass] extra/nested/b.rs",
+            "[run-pass] run-pass/a.rs"
+        ]
+    );
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.passed(), 3, "{:?}", summary.failures().next());
+    // There is no `auxiliary` next to `extra/a.rs`, so it gets the helper from `aux_dirs`
+    let failure = summary.failures().next().unwrap();
+    assert_eq!(failure.name, "[run-pass] extra/a.rs");
+
+    // Names of roots must not clash
+    config.extra_src_bases = vec![root.join("other/run-pass")];
+    assert!(list::listed_tests(&config).is_err());
+    config.extra_src_bases = vec![root.join("shared")];
+    fs::create_dir_all(builder.root.join("shared")).unwrap();
+    assert!(list::listed_tests(&config).is_err());
+}
@@ -0,0 +1,65 @@
+//! Tests for the directive checks of `strict_headers`
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+
+#[test]
+fn test_strict_headers() {
+    let builder = testsuite("run-pass");
+    let config = Config {
+        mode: "run-pass".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_strict_headers"),
+        strict_headers: true,
+        ..Config::default()
+    };
+
+    builder.mk_file(
+        "known.rs",
+        "//@ compile-flags: --cfg foo\n//@ ignore-wasm32\n//@ ignore-stage9\n//@ ignore-sgx\n//@ ignore-x86_64-unknown-uefi\n//@[a] normalize-stderr-64bit: \"x\" -> \"y\"\n// not a directive\nfn main() {}\n",
+    );
+    builder.mk_file("typo.rs", "//@ compile-flag: --cfg foo\nfn main() {}\n");
+    builder.mk_file("unknown.rs", "//@ frobnicate\nfn main() {}\n");
+    builder.mk_file("late.rs", "fn main() {}\n//@ compile-flags: --cfg foo\n");
+    builder.mk_file("condition.rs", "//@ ignore-linx\nfn main() {}\n");
+    builder.mk_file("sanitizer.rs", "//@ needs-sanitizer-adress\nfn main() {}\n");
+    // Ignored tests are checked too
+    builder.mk_file(
+        "ignored.rs",
+        "//@ ignore-test\n//@ needs-sanitizer-thread\n//@ only-x86-64\nfn main() {}\n",
+    );
+
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!(summary.passed(), 1);
+    let messages = summary
+        .failures()
+        .map(|failure| (failure.name.as_str(), failure.message.as_deref().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(messages.len(), 6);
+    for (name, message) in messages {
+        let expected = match name {
+            "[run-pass] run-pass/typo.rs" => {
+                "typo.rs:1: unknown directive `compile-flag`, did you mean `compile-flags`?"
+            }
+            "[run-pass] run-pass/unknown.rs" => "unknown.rs:1: unknown directive `frobnicate`",
+            "[run-pass] run-pass/condition.rs" => {
+                "condition.rs:1: unknown directive `ignore-linx`, did you mean `ignore-linux`?"
+            }
+            "[run-pass] run-pass/sanitizer.rs" => {
+                "sanitizer.rs:1: unknown directive \
+                 `needs-sanitizer-adress`, did you mean `needs-sanitizer-address`?"
+            }
+            "[run-pass] run-pass/ignored.rs" => "ignored.rs:3: unknown directive `only-x86-64`",
+            "[run-pass] run-pass/late.rs" => {
+                "late.rs:2: directive `compile-flags` comes after the first `fn` or `mod`"
+            }
+            _ => panic!("unexpected failure of {}", name),
+        };
+        assert!(message.contains(expected), "{}", message);
+    }
+}
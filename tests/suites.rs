@@ -0,0 +1,81 @@
+//! Tests for loading suites from a `compiletest.toml`
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::suites::{self, LoadError, CONFIG_FILE};
+use crate::test_support::testsuite;
+use std::env;
+use std::fs;
+use std::time::Duration;
+
+#[test]
+fn test_load_suites() {
+    let builder = testsuite("suites");
+    for dir in ["ui", "pass"] {
+        fs::create_dir_all(builder.root.join(dir)).unwrap();
+    }
+    builder.mk_file(
+        CONFIG_FILE,
+        r#"
+build_base = "build"
+timeout = 60
+
+[[suite]]
+mode = "ui"
+src_base = "ui"
+edition = "2021"
+normalize_stderr = [['(?s)^warning: unused variable: `abc`.*', "unused `XYZ`\n"]]
+
+[[suite]]
+name = "pass"
+mode = "run-pass"
+src_base = "pass"
+color = "never"
+"#,
+    );
+    builder.mk_file(
+        "ui/warn.rs",
+        "#[warn(unused_variables)]\nfn main() {\n    let abc = 1;\n}\n",
+    );
+    builder.mk_file("ui/warn.stderr", "unused `XYZ`\n");
+
+    let path = builder.root.join(CONFIG_FILE);
+    let loaded = suites::load(&path).unwrap();
+    assert_eq!(loaded.len(), 2);
+    assert_eq!(loaded[0].name, "ui");
+    assert_eq!(loaded[0].config.src_base, builder.root.join("ui"));
+    assert_eq!(loaded[0].config.build_base, builder.root.join("build"));
+    assert_eq!(loaded[0].config.edition.as_deref(), Some("2021"));
+    assert_eq!(loaded[0].config.timeout, Some(Duration::from_secs(60)));
+    assert_eq!(loaded[1].name, "pass");
+    assert_eq!(loaded[1].config.mode.to_string(), "run-pass");
+
+    fs::create_dir_all(&loaded[0].config.build_base).unwrap();
+    let summary = compiletest::run_tests_with_summary(&loaded[0].config).unwrap();
+    assert_eq!(summary.passed(), 1, "{:?}", summary.failures().next());
+
+    // Overrides from the environment apply to every suite, and unrelated variables are ignored
+    env::set_var("COMPILETEST_TARGET_RUSTCFLAGS", "-D warnings");
+    env::set_var("COMPILETEST_STRICT_HEADERS", "true");
+    env::set_var("COMPILETEST_FORCE_STAGE0", "1");
+    let loaded = suites::load(&path);
+    env::remove_var("COMPILETEST_TARGET_RUSTCFLAGS");
+    env::remove_var("COMPILETEST_STRICT_HEADERS");
+    env::remove_var("COMPILETEST_FORCE_STAGE0");
+    for suite in loaded.unwrap() {
+        assert_eq!(
+            suite.config.target_rustcflags.as_deref(),
+            Some("-D warnings")
+        );
+        assert!(suite.config.strict_headers);
+    }
+
+    builder.mk_file(CONFIG_FILE, "[[suite]]\nmode = \"ui\"\nsrc_dir = \"ui\"\n");
+    match suites::load(&path) {
+        Err(LoadError::Invalid(_, e)) => assert!(e.contains("unknown field `src_dir`"), "{}", e),
+        _ => panic!("unknown field was accepted"),
+    }
+}
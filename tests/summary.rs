@@ -0,0 +1,110 @@
+//! Tests for `run_tests_with_summary`
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::{Config, TestStatus};
+use crate::test_support::{global_root, testsuite};
+
+#[test]
+fn test_summary_reports_each_outcome() {
+    let builder = testsuite("ui");
+    let config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base"),
+        ..Config::default()
+    };
+
+    builder.mk_file("pass.rs", "fn main() {}\n");
+    builder.mk_file(
+        "fail.rs",
+        r#"
+            #[warn(unused_variables)]
+            fn main() {
+                let abc = "unexpected warning";
+            }
+        "#,
+    );
+    builder.mk_file("skip.rs", "// ignore-test\nfn main() {}\n");
+
+    let summary = compiletest::run_tests_with_summary(&config).expect("run failed");
+
+    assert_eq!(summary.passed(), 1);
+    assert_eq!(summary.failed(), 1);
+    assert_eq!(summary.ignored(), 1);
+    assert!(!summary.is_success());
+
+    let failure = summary.failures().next().unwrap();
+    assert_eq!(failure.name, "[ui] ui/fail.rs");
+    assert_eq!(failure.status, TestStatus::Failed);
+    let message = failure.message.as_ref().unwrap();
+    assert!(message.contains("1 errors occurred comparing output."));
+    assert!(message.contains("unused variable"));
+}
+
+#[test]
+fn test_write_report() {
+    let builder = testsuite("ui");
+    let config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_report"),
+        write_report: true,
+        ..Config::default()
+    };
+    std::fs::create_dir_all(&config.build_base).unwrap();
+
+    builder.mk_file("pass.rs", "fn main() {}\n");
+    builder.mk_file("fail.rs", "fn main() { let _: u8 = \"<&>\"; }\n");
+
+    let summary = compiletest::run_tests_with_summary(&config).expect("run failed");
+    assert_eq!(summary.failed(), 1);
+
+    let junit = std::fs::read_to_string(compiletest::report::junit_report_path(&config)).unwrap();
+    assert!(junit.contains(r#"failures="1" tests="2" skipped="0""#));
+    assert!(junit.contains(r#"<testcase classname="ui" name="[ui] ui/pass.rs""#));
+    assert!(junit.contains("<failure type=\"assert\""));
+    assert!(junit.contains("mismatched types"));
+
+    let json = std::fs::read_to_string(compiletest::report::json_report_path(&config)).unwrap();
+    let lines = json.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with(r#"{"type":"test","name":"[ui] ui/fail.rs","mode":"ui""#));
+    assert!(lines[0].contains(r#""status":"failed""#));
+    assert!(lines[1].contains(r#""status":"passed""#));
+    assert!(lines[2].starts_with(r#"{"type":"suite","passed":1,"failed":1,"ignored":0"#));
+}
+
+#[test]
+fn test_summary_reports_fatal_errors() {
+    let builder = testsuite("compile-fail");
+    let config = Config {
+        mode: "compile-fail".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_fatal"),
+        ..Config::default()
+    };
+
+    builder.mk_file(
+        "both.rs",
+        r#"
+            // revisions: a
+            //[a] error-pattern: mismatched types
+            fn main() {
+                let _: u8 = ""; //[a]~ ERROR mismatched types
+            }
+        "#,
+    );
+
+    let summary = compiletest::run_tests_with_summary(&config).expect("run failed");
+
+    let failure = summary.failures().next().unwrap();
+    assert_eq!(failure.revision.as_deref(), Some("a"));
+    assert_eq!(
+        failure.message.as_deref(),
+        Some("both error pattern and expected errors specified")
+    );
+}
@@ -0,0 +1,90 @@
+//! Tests for the `only-cfg` and `ignore-cfg` headers
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::list;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+
+#[test]
+fn test_cfg_directives() {
+    let builder = testsuite("ui");
+    let mut config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_target_cfg"),
+        ..Config::default()
+    };
+
+    builder.mk_file(
+        "only_unwind.rs",
+        "// only-cfg: panic=\"unwind\"\nfn main() {}\n",
+    );
+    builder.mk_file(
+        "ignore_abort.rs",
+        "// ignore-cfg: panic=abort\nfn main() {}\n",
+    );
+    builder.mk_file("only_custom.rs", "// only-cfg: custom\nfn main() {}\n");
+    let os = if cfg!(windows) { "windows" } else { "unix" };
+    builder.mk_file(
+        "ignore_os.rs",
+        &format!("// ignore-cfg: {}\nfn main() {{}}\n", os),
+    );
+
+    let ignored = |config: &Config| {
+        list::listed_tests(config)
+            .unwrap()
+            .into_iter()
+            .filter(|test| test.ignored)
+            .map(|test| test.name)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        ignored(&config),
+        ["[ui] ui/ignore_os.rs", "[ui] ui/only_custom.rs"]
+    );
+
+    // The cfg reflects `target_rustcflags`
+    config.target_rustcflags = Some("-C panic=abort --cfg custom".to_owned());
+    assert_eq!(
+        ignored(&config),
+        [
+            "[ui] ui/ignore_abort.rs",
+            "[ui] ui/ignore_os.rs",
+            "[ui] ui/only_unwind.rs"
+        ]
+    );
+}
+
+#[test]
+fn test_cfg_errors_fail_the_test() {
+    let builder = testsuite("run-pass");
+    let mut config = Config {
+        mode: "run-pass".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_target_cfg_errors"),
+        ..Config::default()
+    };
+
+    builder.mk_file("pass.rs", "fn main() {}\n");
+    builder.mk_file("empty.rs", "// only-cfg:\nfn main() {}\n");
+
+    let summary = compiletest::run_tests_with_summary(&config).expect("run failed");
+    assert_eq!(summary.passed(), 1);
+    let failure = summary.failures().next().expect("test should have failed");
+    assert_eq!(failure.name, "[run-pass] run-pass/empty.rs");
+    let message = failure.message.as_ref().unwrap();
+    assert!(message.contains("malformed cfg directive"));
+
+    // A compiler that can't print the cfg fails the tests that need it, not the whole run
+    builder.mk_file("empty.rs", "// ignore-cfg: unix\nfn main() {}\n");
+    config.target = "no-such-target".to_owned();
+    let summary = compiletest::run_tests_with_summary(&config).expect("run failed");
+    let failure = summary.failures().next().expect("test should have failed");
+    assert_eq!(failure.name, "[run-pass] run-pass/empty.rs");
+    let message = failure.message.as_ref().unwrap();
+    assert!(message.contains("--print cfg --target no-such-target`"));
+}
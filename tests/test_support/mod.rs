@@ -3,6 +3,9 @@
 //! Inspired by cargo's `cargo-test-support` crate:
 //! https://github.com/rust-lang/cargo/tree/master/crates/cargo-test-support
 
+// Not every test crate uses every helper.
+#![allow(dead_code)]
+
 use std::cell::RefCell;
 use std::env;
 use std::fs;
@@ -15,7 +18,7 @@ use std::sync::{
 static COMPILETEST_INTEGRATION_TEST_DIR: &str = "cit";
 
 thread_local! {
-    static TEST_ID: RefCell<Option<usize>> = RefCell::new(None);
+    static TEST_ID: RefCell<Option<usize>> = const { RefCell::new(None) };
 }
 
 pub fn global_root() -> &'static Path {
@@ -59,7 +62,7 @@ impl TestsuiteBuilder {
     /// Creates a new file to be used for the integration test
     pub fn mk_file(&self, path: &str, body: &str) {
         self.root.mkdir_p();
-        fs::write(self.root.join(&path), &body)
+        fs::write(self.root.join(path), body)
             .unwrap_or_else(|e| panic!("could not create file {}: {}", path, e));
     }
 
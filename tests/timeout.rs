@@ -0,0 +1,65 @@
+//! Tests for the `timeout` option and header
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_timeout_kills_runaway_test() {
+    let builder = testsuite("run-pass");
+    let config = Config {
+        mode: "run-pass".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base"),
+        timeout: Some(Duration::from_secs(60)),
+        ..Config::default()
+    };
+
+    builder.mk_file(
+        "spin.rs",
+        r#"
+            // timeout: 1
+            fn main() {
+                println!("spinning");
+                loop {}
+            }
+        "#,
+    );
+
+    let start = Instant::now();
+    let summary = compiletest::run_tests_with_summary(&config).expect("run failed");
+    assert!(start.elapsed() < Duration::from_secs(30));
+
+    let failure = summary.failures().next().expect("test should have failed");
+    let message = failure.message.as_ref().unwrap();
+    assert!(message.starts_with("error: timed out after 1 s"));
+    assert!(message.contains("spinning"));
+}
+
+#[test]
+fn test_malformed_timeout_fails_the_test() {
+    let builder = testsuite("run-pass");
+    let config = Config {
+        mode: "run-pass".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_malformed_timeout"),
+        // Reads the headers while collecting the tests, which mustn't abort the run
+        skip_up_to_date: true,
+        ..Config::default()
+    };
+
+    builder.mk_file("pass.rs", "// timeout: 60\nfn main() {}\n");
+    builder.mk_file("malformed.rs", "// timeout: soon\nfn main() {}\n");
+
+    let summary = compiletest::run_tests_with_summary(&config).expect("run failed");
+    assert_eq!(summary.passed(), 1);
+    let failure = summary.failures().next().expect("test should have failed");
+    assert_eq!(failure.name, "[run-pass] run-pass/malformed.rs");
+    let message = failure.message.as_ref().unwrap();
+    assert!(message.contains("malformed timeout directive: `soon` is not a number of seconds"));
+}
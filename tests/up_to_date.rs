@@ -0,0 +1,75 @@
+//! Tests for the `skip_up_to_date` option
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::Config;
+use crate::test_support::{global_root, testsuite};
+
+#[test]
+fn test_skip_up_to_date() {
+    let builder = testsuite("ui");
+    let mut config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_up_to_date"),
+        skip_up_to_date: true,
+        ..Config::default()
+    };
+
+    builder.mk_file("unchanged.rs", "fn main() {}\n");
+    builder.mk_file("changed.rs", "fn main() {}\n");
+
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!((summary.passed(), summary.ignored()), (2, 0));
+
+    // Nothing changed, so both tests are skipped
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!((summary.passed(), summary.ignored()), (0, 2));
+
+    // Touching a test file or adding an expected output file reruns that test
+    builder.mk_file("changed.rs", "fn main() { }\n");
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!((summary.passed(), summary.ignored()), (1, 1));
+    assert_eq!(summary.outcomes[0].name, "[ui] ui/changed.rs");
+    assert_eq!(summary.outcomes[0].status, compiletest::TestStatus::Passed);
+
+    builder.mk_file("unchanged.stdout", "");
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!((summary.passed(), summary.ignored()), (1, 1));
+
+    // As does changing the flags
+    config.target_rustcflags = Some("-O".to_owned());
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!((summary.passed(), summary.ignored()), (2, 0));
+}
+
+#[test]
+fn test_linked_library_change_reruns_tests() {
+    let builder = testsuite("ui");
+    let lib_dir = global_root().join("up_to_date_deps");
+    std::fs::create_dir_all(&lib_dir).unwrap();
+    let config = Config {
+        mode: "ui".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_up_to_date_libs"),
+        skip_up_to_date: true,
+        target_rustcflags: Some(format!("-L dependency={}", lib_dir.display())),
+        ..Config::default()
+    };
+
+    builder.mk_file("test.rs", "fn main() {}\n");
+    std::fs::write(lib_dir.join("libfoo.rlib"), "").unwrap();
+
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!((summary.passed(), summary.ignored()), (1, 0));
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!((summary.passed(), summary.ignored()), (0, 1));
+
+    // Rebuilding a library in a `-L` directory reruns the test
+    std::fs::write(lib_dir.join("libfoo.rlib"), "").unwrap();
+    let summary = compiletest::run_tests_with_summary(&config).unwrap();
+    assert_eq!((summary.passed(), summary.ignored()), (1, 0));
+}
@@ -0,0 +1,81 @@
+//! Tests for `watch::Watcher`
+
+#![cfg_attr(feature = "rustc", feature(rustc_private))]
+
+extern crate compiletest_rs as compiletest;
+
+mod test_support;
+use crate::compiletest::watch::Watcher;
+use crate::compiletest::{Config, TestSummary};
+use crate::test_support::{global_root, testsuite};
+use std::fs;
+
+fn names(summary: Option<TestSummary>) -> Vec<String> {
+    summary
+        .expect("no tests ran")
+        .outcomes
+        .into_iter()
+        .map(|outcome| outcome.name)
+        .collect()
+}
+
+#[test]
+fn test_watcher() {
+    let builder = testsuite("run-pass");
+    let config = Config {
+        mode: "run-pass".parse().expect("Invalid mode"),
+        src_base: builder.root.clone(),
+        build_base: global_root().join("build_base_watch"),
+        ..Config::default()
+    };
+    fs::create_dir_all(builder.root.join("auxiliary/auxiliary")).unwrap();
+    builder.mk_file(
+        "uses_aux.rs",
+        "// aux-build: helper.rs\nextern crate helper;\nfn main() {}\n",
+    );
+    builder.mk_file("plain.rs", "fn main() {}\n");
+    let helper = "// aux-build: inner.rs\nextern crate inner;\npub fn f() {}\n";
+    builder.mk_file("auxiliary/helper.rs", helper);
+    builder.mk_file("auxiliary/auxiliary/inner.rs", "pub fn f() {}\n");
+    builder.mk_file("lib.rlib", "");
+
+    let mut watcher = Watcher::new(&config).rerun_all_on([builder.root.join("lib.rlib")]);
+    assert_eq!(
+        names(watcher.run_changed().unwrap()),
+        [
+            "[run-pass] run-pass/plain.rs",
+            "[run-pass] run-pass/uses_aux.rs"
+        ]
+    );
+    assert!(watcher.run_changed().unwrap().is_none());
+
+    // Changing an aux-build reruns the tests that use it
+    builder.mk_file(
+        "auxiliary/helper.rs",
+        &format!("{}pub fn g() {{}}\n", helper),
+    );
+    assert_eq!(
+        names(watcher.run_changed().unwrap()),
+        ["[run-pass] run-pass/uses_aux.rs"]
+    );
+
+    // And so does changing an aux-build of that aux-build
+    builder.mk_file("auxiliary/auxiliary/inner.rs", "pub fn g() {}\n");
+    assert_eq!(
+        names(watcher.run_changed().unwrap()),
+        ["[run-pass] run-pass/uses_aux.rs"]
+    );
+
+    // As do new tests and new expected output files
+    builder.mk_file("new.rs", "fn main() {}\n");
+    builder.mk_file("plain.stderr", "");
+    assert_eq!(
+        names(watcher.run_changed().unwrap()),
+        ["[run-pass] run-pass/new.rs", "[run-pass] run-pass/plain.rs"]
+    );
+    assert!(watcher.run_changed().unwrap().is_none());
+
+    // Rebuilding the library reruns everything
+    builder.mk_file("lib.rlib", "rebuilt");
+    assert_eq!(names(watcher.run_changed().unwrap()).len(), 3);
+}